@@ -0,0 +1,395 @@
+use std::env;
+use std::f32::consts::PI;
+use std::str::FromStr;
+use std::time::Duration;
+use rand::Rng;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpStream, UdpSocket};
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::mpsc;
+use tokio::time::Instant;
+use gyrogun_server::client::calibration::grid_targets;
+use gyrogun_server::client::datagram::SESSION_ORIENTATION;
+use gyrogun_server::client::discovery::{ANNOUNCE, DISCOVERY_PORT, PROBE};
+use gyrogun_server::client::downlink;
+use gyrogun_server::client::handshake::{self, Capabilities, PROTOCOL_VERSION};
+use gyrogun_server::client::mac;
+use gyrogun_server::client::raw_message::{HELLO, PONG, TIMED_CLICK};
+
+/*
+    Virtual controllers speaking the real protocol: Hello handshake over TCP, calibration clicks
+    as the server walks through the init phases, and a 60Hz orientation stream over UDP, signed when the server agrees to AUTH.
+    Each one stands somewhere in front of a flat screen and aims at points picked by a motion profile.
+
+    simulated_controller <count> <width> <height> <server address|discover> <sweep|jitter|random> [pin] [cant]
+
+    A cant in degrees holds every gun rolled clockwise by that much and reports yaw and pitch
+    in the gun's frame, the way a ROLL controller does.
+ */
+
+const STREAM_INTERVAL: Duration = Duration::from_millis(16);
+const CLICK_DELAY: Duration = Duration::from_millis(500);
+const DISCOVERY_TIMEOUT: Duration = Duration::from_secs(3);
+
+#[derive(Copy, Clone, Debug)]
+enum Profile {
+    Sweep,
+    Jitter,
+    Random,
+}
+
+impl FromStr for Profile {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sweep" => Ok(Profile::Sweep),
+            "jitter" => Ok(Profile::Jitter),
+            "random" => Ok(Profile::Random),
+            _ => Err(()),
+        }
+    }
+}
+
+/* What a PhaseChanged downlink asks for */
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Phase {
+    WaitMonitor,
+    WaitFirstPoint,
+    WaitSecondPoint,
+    Finalize,
+    Game,
+    Results,
+    Lobby,
+    /* Which grid target, and how many there are */
+    WaitGridPoint(u8, u8),
+}
+
+impl Phase {
+    fn from_frame(buf: &[u8; 16]) -> Option<Phase> {
+        let word = |i: usize| u32::from_be_bytes([buf[i], buf[i + 1], buf[i + 2], buf[i + 3]]);
+        match word(4) {
+            downlink::PHASE_WAIT_MONITOR => Some(Phase::WaitMonitor),
+            downlink::PHASE_WAIT_FIRST_POINT => Some(Phase::WaitFirstPoint),
+            downlink::PHASE_WAIT_SECOND_POINT => Some(Phase::WaitSecondPoint),
+            downlink::PHASE_FINALIZE => Some(Phase::Finalize),
+            downlink::PHASE_GAME => Some(Phase::Game),
+            downlink::PHASE_RESULTS => Some(Phase::Results),
+            downlink::PHASE_LOBBY => Some(Phase::Lobby),
+            downlink::PHASE_WAIT_GRID_POINT => Some(Phase::WaitGridPoint(word(8) as u8, word(12) as u8)),
+            _ => None,
+        }
+    }
+}
+
+enum Downlink {
+    Phase(Phase),
+    Ping(u32),
+}
+
+/*
+    Shooter stands at (x, y, h) with the screen in the y = 0 plane, screen center at the origin.
+    Yaw is clockwise with the screen straight ahead at base_yaw, pitch is positive pointing down.
+ */
+struct Aim {
+    shooter: (f32, f32, f32),
+    base_yaw: f32,
+    cant: f32,
+}
+
+impl Aim {
+    fn at(&self, target: (f32, f32)) -> (f32, f32, f32) {
+        let (x, y, h) = self.shooter;
+        let (dx, dy, dz) = (target.0 - x, -y, target.1 - h);
+
+        let right = dx.atan2(dy) * 180.0 / PI;
+        let down = -dz.atan2((dx * dx + dy * dy).sqrt()) * 180.0 / PI;
+
+        /* Turned into the canted gun's frame, the inverse of what the server undoes */
+        let (sin, cos) = (self.cant * PI / 180.0).sin_cos();
+        let (right, down) = (right * cos + down * sin, down * cos - right * sin);
+        ((self.base_yaw + right).rem_euclid(360.0), down, self.cant)
+    }
+
+    fn monitor(&self) -> (f32, f32, f32) {
+        (self.base_yaw, 0.0, self.cant)
+    }
+}
+
+struct Motion {
+    profile: Profile,
+    window_size: (f32, f32),
+    offset: f32,
+    target: (f32, f32),
+    previous: (f32, f32),
+    target_since: Instant,
+}
+
+impl Motion {
+    /* Point on screen being aimed at, in screen coordinates centered on the screen with y up */
+    fn sample(&mut self, now: Instant, started: Instant) -> (f32, f32) {
+        let (w, h) = self.window_size;
+        let t = (now - started).as_secs_f32() + self.offset;
+        let mut rng = rand::thread_rng();
+
+        match self.profile {
+            Profile::Sweep => ((t * 0.7).sin() * w * 0.45, (t * 1.1).sin() * h * 0.4),
+            Profile::Jitter => {
+                let (x, y) = ((t * 0.2).sin() * w * 0.2, (t * 0.3).cos() * h * 0.2);
+                (x + rng.gen_range(-15.0..15.0), y + rng.gen_range(-15.0..15.0))
+            }
+            Profile::Random => {
+                let elapsed = (now - self.target_since).as_secs_f32();
+                if elapsed >= 1.5 {
+                    self.previous = self.target;
+                    self.target = (rng.gen_range(-w * 0.45..w * 0.45), rng.gen_range(-h * 0.45..h * 0.45));
+                    self.target_since = now;
+                }
+                let k = (elapsed / 0.4).min(1.0);
+                let k = k * k * (3.0 - 2.0 * k);
+                (self.previous.0 + (self.target.0 - self.previous.0) * k, self.previous.1 + (self.target.1 - self.previous.1) * k)
+            }
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args: Vec<String> = env::args().collect();
+
+    let count = args.get(1).and_then(|x| u32::from_str(x).ok()).unwrap_or(1);
+    let width = args.get(2).and_then(|x| f32::from_str(x).ok()).unwrap_or(1920.0);
+    let height = args.get(3).and_then(|x| f32::from_str(x).ok()).unwrap_or(1080.0);
+    let server_addr = args.get(4).cloned().unwrap_or(String::from("127.0.0.1:11076"));
+    let profile = args.get(5).and_then(|x| Profile::from_str(x).ok()).unwrap_or(Profile::Sweep);
+    let pin = args.get(6).and_then(|x| u32::from_str(x).ok()).unwrap_or(0);
+    let cant = args.get(7).and_then(|x| f32::from_str(x).ok()).unwrap_or(0.0);
+
+    let server_addr = if server_addr == "discover" {
+        let Some(server_addr) = discover().await else {
+            println!("No server answered the discovery probe");
+            return;
+        };
+        server_addr
+    } else {
+        server_addr
+    };
+
+    println!("Simulating {count} controllers against {server_addr} with {profile:?} motion");
+
+    let mut handles = vec![];
+    for index in 0..count {
+        let aim = Aim {
+            shooter: ((index as f32 - (count - 1) as f32 / 2.0) * 300.0, -height * 2.0, -height * 0.2),
+            base_yaw: (index as f32 * 97.0 + 10.0) % 360.0,
+            cant,
+        };
+        let motion = Motion {
+            profile,
+            window_size: (width, height),
+            offset: index as f32 * 1.7,
+            target: (0.0, 0.0),
+            previous: (0.0, 0.0),
+            target_since: Instant::now(),
+        };
+        handles.push(tokio::spawn(run(index, server_addr.clone(), pin, (width, height), aim, motion)));
+    }
+
+    for handle in handles {
+        handle.await.ok();
+    }
+}
+
+async fn run(index: u32, server_addr: String, pin: u32, window_size: (f32, f32), aim: Aim, mut motion: Motion) {
+    let Ok(tcp_sock) = TcpStream::connect(&server_addr).await else {
+        println!("Sim {index}: can't reach {server_addr}");
+        return;
+    };
+    let (mut tcp_read, mut tcp_write) = tcp_sock.into_split();
+
+    let name = format!("Sim {}", index + 1);
+    let mut hello = vec![];
+    hello.extend(HELLO.to_be_bytes());
+    hello.extend(PROTOCOL_VERSION.to_be_bytes());
+    let capabilities = if aim.cant != 0.0 { Capabilities::AUTH | Capabilities::ROLL } else { Capabilities::AUTH };
+    hello.extend(capabilities.to_be_bytes());
+    hello.extend(index.to_be_bytes());
+    hello.extend(0u16.to_be_bytes());
+    hello.extend((name.len() as u16).to_be_bytes());
+    hello.extend(name.as_bytes());
+    hello.extend(pin.to_be_bytes());
+    /* Stable across runs, so a simulated player finds its saved calibration again */
+    let id = format!("sim-{index}");
+    hello.extend((id.len() as u16).to_be_bytes());
+    hello.extend(id.as_bytes());
+    if tcp_write.write_all(&hello).await.is_err() {
+        return;
+    }
+
+    let mut buf = [0u8; 16];
+    if tcp_read.read_exact(&mut buf).await.is_err() {
+        println!("Sim {index}: connection closed during handshake");
+        return;
+    }
+    match i32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) {
+        handshake::ACCEPT => {}
+        handshake::REJECT => {
+            println!("Sim {index}: rejected with reason {}", u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]));
+            return;
+        }
+        x => {
+            println!("Sim {index}: unexpected reply type {x}");
+            return;
+        }
+    }
+    let assigned = u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]);
+    let token = u32::from_be_bytes([buf[12], buf[13], buf[14], buf[15]]);
+    let mut key = None;
+    if Capabilities::from_bits(u16::from_be_bytes([buf[6], buf[7]])).has(Capabilities::AUTH) {
+        let mut buf = [0u8; mac::KEY_LEN];
+        if tcp_read.read_exact(&mut buf).await.is_err() {
+            return;
+        }
+        key = Some(buf);
+    }
+    println!("Sim {index}: joined as id {assigned} with session {token:08x}{}", if key.is_some() { ", signing datagrams" } else { "" });
+
+    let (downlink_tx, mut downlink_rx) = mpsc::unbounded_channel();
+    tokio::spawn(async move {
+        let mut buf = [0u8; 16];
+        while tcp_read.read_exact(&mut buf).await.is_ok() {
+            let word = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+            match i32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) {
+                downlink::PHASE_CHANGED => {
+                    if let Some(phase) = Phase::from_frame(&buf) {
+                        downlink_tx.send(Downlink::Phase(phase)).ok();
+                    }
+                }
+                downlink::PING => {
+                    downlink_tx.send(Downlink::Ping(word)).ok();
+                }
+                _ => {}
+            }
+        }
+    });
+
+    let Ok(udp_sock) = UdpSocket::bind("0.0.0.0:0").await else {
+        return;
+    };
+
+    let started = Instant::now();
+    let mut stream = tokio::time::interval(STREAM_INTERVAL);
+    let mut seq = 0u32;
+    let mut phase = Phase::Lobby;
+    let mut click_at = Some(Instant::now() + CLICK_DELAY);
+    let (_, h) = window_size;
+
+    loop {
+        tokio::select! {
+            _ = stream.tick() => {}
+            downlink = downlink_rx.recv() => {
+                let new_phase = match downlink {
+                    Some(Downlink::Phase(x)) => x,
+                    Some(Downlink::Ping(id)) => {
+                        if pong(&mut tcp_write, id, clock(started)).await.is_err() {
+                            return;
+                        }
+                        continue;
+                    }
+                    None => {
+                        println!("Sim {index}: server closed the connection");
+                        return;
+                    }
+                };
+                println!("Sim {index}: phase {new_phase:?}");
+                phase = new_phase;
+                click_at = (phase != Phase::Results).then(|| Instant::now() + CLICK_DELAY);
+                continue;
+            }
+        }
+
+        let now = Instant::now();
+        let orientation = match phase {
+            Phase::WaitMonitor => aim.monitor(),
+            Phase::WaitFirstPoint => aim.at((-h / 2.0, 0.0)),
+            Phase::WaitSecondPoint => aim.at((h / 2.0, 0.0)),
+            Phase::WaitGridPoint(point, count) => aim.at(grid_targets(count, window_size).get(point as usize).copied().unwrap_or_default()),
+            _ => aim.at(motion.sample(now, started)),
+        };
+
+        let mut datagram = vec![];
+        datagram.extend(SESSION_ORIENTATION.to_be_bytes());
+        datagram.extend(token.to_be_bytes());
+        datagram.extend(seq.to_be_bytes());
+        datagram.extend(clock(started).to_be_bytes());
+        push_orientation(&mut datagram, orientation);
+        if let Some(key) = &key {
+            datagram.extend(mac::sign(key, &datagram));
+        }
+        udp_sock.send_to(&datagram, &server_addr).await.ok();
+        seq = seq.wrapping_add(1);
+
+        if click_at.is_some_and(|x| now >= x) {
+            if click(&mut tcp_write, orientation, clock(started)).await.is_err() {
+                return;
+            }
+            /* Lobby and calibration want one click each, the game gets shot at now and then */
+            click_at = (phase == Phase::Game).then(|| now + Duration::from_millis(rand::thread_rng().gen_range(300..1500)));
+        }
+    }
+}
+
+async fn click(tcp_write: &mut OwnedWriteHalf, orientation: (f32, f32, f32), timestamp: u32) -> std::io::Result<()> {
+    let mut buf = vec![];
+    buf.extend(TIMED_CLICK.to_be_bytes());
+    buf.extend(timestamp.to_be_bytes());
+    push_orientation(&mut buf, orientation);
+    tcp_write.write_all(&buf).await
+}
+
+async fn pong(tcp_write: &mut OwnedWriteHalf, id: u32, timestamp: u32) -> std::io::Result<()> {
+    let mut buf = vec![];
+    buf.extend(PONG.to_be_bytes());
+    buf.extend(id.to_be_bytes());
+    buf.extend(timestamp.to_be_bytes());
+    buf.extend([0u8; 4]);
+    tcp_write.write_all(&buf).await
+}
+
+/* Datagrams, clicks and pongs are all stamped with this, ms since the controller started */
+fn clock(started: Instant) -> u32 {
+    started.elapsed().as_millis() as u32
+}
+
+fn push_orientation(buf: &mut Vec<u8>, (yaw, pitch, roll): (f32, f32, f32)) {
+    buf.extend(yaw.to_be_bytes());
+    buf.extend(pitch.to_be_bytes());
+    buf.extend(roll.to_be_bytes());
+}
+
+/*
+    Broadcasts a probe and takes the first announcement back, see client/discovery.rs
+ */
+async fn discover() -> Option<String> {
+    let sock = UdpSocket::bind("0.0.0.0:0").await.ok()?;
+    sock.set_broadcast(true).ok()?;
+    sock.send_to(&PROBE.to_be_bytes(), ("255.255.255.255", DISCOVERY_PORT)).await.ok()?;
+
+    let mut buf = [0u8; 64];
+    let deadline = Instant::now() + DISCOVERY_TIMEOUT;
+    loop {
+        let (n, addr) = tokio::time::timeout_at(deadline, sock.recv_from(&mut buf)).await.ok()?.ok()?;
+        if n < 16 || i32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) != ANNOUNCE {
+            continue;
+        }
+
+        let port = u16::from_be_bytes([buf[6], buf[7]]);
+        let name_len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+        let name = String::from_utf8_lossy(&buf[16..n.min(16 + name_len)]);
+        println!("Found \"{name}\" at {}:{port} with {} free slots", addr.ip(), buf[10]);
+        if buf[13] == 1 {
+            println!("It wants a pairing PIN, pass one after the motion profile");
+        }
+        return Some(format!("{}:{port}", addr.ip()));
+    }
+}
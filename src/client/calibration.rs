@@ -0,0 +1,394 @@
+use std::str::FromStr;
+use macroquad::math::{Mat3, Vec3};
+use crate::client::init::InitData;
+use crate::client::orientation::{Orientation, ScreenFrame};
+use crate::client::{PosCoord, ShooterCoord};
+
+/* Grid targets sit this far from the screen center, as a fraction of the screen's width and height */
+const GRID_INSET: f32 = 0.35;
+/* Furthest the fit turns the screen away from where the monitor calibration pointed, in radians */
+const MAX_YAW_OFFSET: f32 = 10.0 * std::f32::consts::PI / 180.0;
+const YAW_STEP: f32 = 0.5 * std::f32::consts::PI / 180.0;
+/* How far apart, in degrees as seen from the shooter, the heights the two side points give may be */
+const MAX_PITCH_MISMATCH: f32 = 3.0;
+/* Least yaw between the two side points, in degrees. Any closer and the shooter runs off to infinity */
+const MIN_SPREAD: f32 = 2.0;
+/* Where the shooter may stand, in screen heights from the screen's center, as the side points are that far apart */
+const MIN_DISTANCE: f32 = 0.25;
+const MAX_DISTANCE: f32 = 20.0;
+/* Least cosine between the aim and the screen's normal that still meets the screen, about 89.4 degrees off */
+const MIN_FACING: f32 = 0.01;
+
+/* Where an aim that never meets the screen lands, far enough out that nothing is drawn or shot there */
+pub const OFF_SCREEN: PosCoord = (1.0e6, 1.0e6);
+
+/*
+    Three points is the quick monitor, left and right flow. A grid asks for 5 or 9 targets
+    after the monitor step and fits all of them, so one shaky click gets outvoted.
+ */
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Mode {
+    ThreePoint,
+    Grid(u8),
+}
+
+impl FromStr for Mode {
+    type Err = ();
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "3" => Ok(Mode::ThreePoint),
+            "5" => Ok(Mode::Grid(5)),
+            "9" => Ok(Mode::Grid(9)),
+            _ => Err(()),
+        }
+    }
+}
+
+/*
+    What the least squares fit came up with. The residual is how far, in pixels on average,
+    the calibrated aim of each sample lands from its target.
+ */
+#[derive(Copy, Clone, Debug)]
+pub struct Fit {
+    pub shooter: ShooterCoord,
+    pub yaw_offset: f32,
+    pub residual: f32,
+}
+
+/*
+    The three point solve. Mismatch is the angle, in degrees from where the shooter stands,
+    between the heights the left and the right point put them at.
+ */
+#[derive(Copy, Clone, Debug)]
+pub struct Sides {
+    pub shooter: ShooterCoord,
+    pub mismatch: f32,
+}
+
+impl Sides {
+    pub fn is_consistent(&self) -> bool {
+        self.mismatch <= MAX_PITCH_MISMATCH
+    }
+}
+
+/*
+    Why side points were turned down, so the player can be told what to do differently
+ */
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Problem {
+    TooNarrow,
+    NoCrossing,
+    TooClose,
+    TooFar,
+}
+
+impl Problem {
+    pub fn explain(&self) -> &'static str {
+        match self {
+            Problem::TooNarrow => "Both circles were hit at almost the same angle, aim at each one in turn",
+            Problem::NoCrossing => "The aims don't meet in front of the screen, take the left circle first, then the right",
+            Problem::TooClose => "That puts you right at the screen, hold still and aim at the middle of each circle",
+            Problem::TooFar => "That puts you too far from the screen, aim at the middle of each circle",
+        }
+    }
+}
+
+/*
+    Targets in screen coordinates centered on the screen with y up.
+    5 is the center then the four corners, 9 a 3 by 3 grid row by row from the top left.
+ */
+pub fn grid_targets(count: u8, window_size: (f32, f32)) -> Vec<PosCoord> {
+    let (w, h) = window_size;
+    let (x, y) = (w * GRID_INSET, h * GRID_INSET);
+
+    match count {
+        5 => vec![(0.0, 0.0), (-x, y), (x, y), (-x, -y), (x, -y)],
+        _ => (0..9).map(|i| (((i % 3) as f32 - 1.0) * x, (1.0 - (i / 3) as f32) * y)).collect(),
+    }
+}
+
+/*
+    The frame screen space is measured in and the screen plane's normal,
+    which faces the way the monitor calibration pointed, turned by the fitted yaw offset
+ */
+pub(super) fn screen_plane(monitor: Orientation, yaw_offset: f32) -> (ScreenFrame, Vec3) {
+    let frame = ScreenFrame::new(monitor).turned(yaw_offset);
+    let towards = frame.local(monitor);
+    (frame, Vec3::new(0.0, towards.y, towards.z).normalize())
+}
+
+/*
+    Intersects the aiming ray with the screen plane.
+    Aiming away from the screen or along it meets nothing, the line behind the gun doesn't count.
+ */
+pub(super) fn project(frame: &ScreenFrame, normal: Vec3, shooter: Vec3, orientation: Orientation) -> PosCoord {
+    let forward = frame.local(orientation).normalize();
+    let facing = forward.dot(normal);
+    if facing <= MIN_FACING {
+        return OFF_SCREEN;
+    }
+    let t = -shooter.dot(normal) / facing;
+    if t <= 0.0 {
+        return OFF_SCREEN;
+    }
+    let hit = shooter + forward * t;
+    let up = Vec3::X.cross(normal);
+
+    (hit.x, hit.dot(up))
+}
+
+/*
+    For a given screen direction, the shooter is the point closest to all the lines running back
+    from each target along the aim that hit it, which is linear. The direction isn't, so it's
+    scanned for and then narrowed down, as a monitor click is as likely to be shaky as any other.
+ */
+pub fn fit(init_data: &InitData) -> Option<Fit> {
+    let samples = init_data.samples();
+    if samples.len() < 3 {
+        return None;
+    }
+    let solve = |yaw_offset| solve(init_data.monitor(), samples, yaw_offset);
+    let residual = |fit: Option<Fit>| fit.map_or(f32::INFINITY, |x| x.residual);
+
+    let steps = (MAX_YAW_OFFSET / YAW_STEP) as i32;
+    let mut best = (-steps..=steps).map(|i| solve(i as f32 * YAW_STEP)).min_by(|a, b| residual(*a).total_cmp(&residual(*b)))??;
+
+    let (mut low, mut high) = (best.yaw_offset - YAW_STEP, best.yaw_offset + YAW_STEP);
+    for _ in 0..24 {
+        let (a, b) = (low + (high - low) / 3.0, high - (high - low) / 3.0);
+        if residual(solve(a)) < residual(solve(b)) {
+            high = b;
+        } else {
+            low = a;
+        }
+    }
+    if let Some(refined) = solve((low + high) / 2.0) {
+        if refined.residual < best.residual {
+            best = refined;
+        }
+    }
+    Some(best)
+}
+
+/*
+    Solves in the same tilted frame screen_pos projects into. Seen along the screen's up,
+    the two aims cross at where the shooter stands, and each one's pitch then says how high.
+    They should agree, the shooter ends up halfway between and the gap is the mismatch.
+    The side points are the correction circles, half the screen's height left and right of its center.
+ */
+pub fn sides(init_data: &InitData) -> Option<Sides> {
+    let (frame, normal) = screen_plane(init_data.monitor(), 0.0);
+    let up = Vec3::X.cross(normal);
+    let half = init_data.window_size().1 / 2.0;
+
+    let a = frame.local(init_data.first_point());
+    let b = frame.local(init_data.second_point());
+    let (a_x, a_n, b_x, b_n) = (a.x, a.dot(normal), b.x, b.dot(normal));
+    if a_n < 1e-3 || b_n < 1e-3 {
+        return None;
+    }
+
+    /* x = -half + n * a_x / a_n = half + n * b_x / b_n, n being along the normal so negative in front of the screen */
+    let n = 2.0 * half / (a_x / a_n - b_x / b_n);
+    let x = -half + n * a_x / a_n;
+    let a_h = n * a.dot(up) / a_n;
+    let b_h = n * b.dot(up) / b_n;
+
+    let distance = (x * x + n * n).sqrt();
+    let mismatch = (a_h - b_h).abs().atan2(distance).to_degrees();
+    let shooter = Vec3::X * x + normal * n + up * (a_h + b_h) / 2.0;
+
+    (n < 0.0 && shooter.is_finite()).then_some(Sides {
+        shooter: (shooter.x, shooter.y, shooter.z),
+        mismatch,
+    })
+}
+
+/*
+    The side points have to be far enough apart to solve with, and put the shooter somewhere they could be standing
+ */
+pub fn check(init_data: &InitData) -> Result<Sides, Problem> {
+    let frame = ScreenFrame::new(init_data.monitor());
+    let a = frame.local(init_data.first_point()).truncate();
+    let b = frame.local(init_data.second_point()).truncate();
+    let spread = a.angle_between(b).abs().to_degrees();
+    if spread.is_nan() || spread < MIN_SPREAD {
+        return Err(Problem::TooNarrow);
+    }
+
+    let sides = sides(init_data).ok_or(Problem::NoCrossing)?;
+    let height = init_data.window_size().1;
+    let (x, y, h) = sides.shooter;
+    let distance = (x * x + y * y + h * h).sqrt() / height;
+    if distance < MIN_DISTANCE {
+        Err(Problem::TooClose)
+    } else if distance > MAX_DISTANCE {
+        Err(Problem::TooFar)
+    } else {
+        Ok(sides)
+    }
+}
+
+fn solve(monitor: Orientation, samples: &[(PosCoord, Orientation)], yaw_offset: f32) -> Option<Fit> {
+    let (frame, normal) = screen_plane(monitor, yaw_offset);
+    let up = Vec3::X.cross(normal);
+
+    let mut a = Mat3::ZERO;
+    let mut b = Vec3::ZERO;
+    for ((x, y), orientation) in samples {
+        let d = frame.local(*orientation).normalize();
+        let target = Vec3::X * *x + up * *y;
+        let away = Mat3::IDENTITY - Mat3::from_cols(d * d.x, d * d.y, d * d.z);
+        a += away;
+        b += away * target;
+    }
+    if a.determinant().abs() < 1e-6 {
+        return None;
+    }
+    let shooter = a.inverse() * b;
+
+    let squared: f32 = samples.iter().map(|((x, y), orientation)| {
+        let (hit_x, hit_y) = project(&frame, normal, shooter, *orientation);
+        (hit_x - x).powi(2) + (hit_y - y).powi(2)
+    }).sum();
+    let residual = (squared / samples.len() as f32).sqrt();
+
+    (shooter.is_finite() && residual.is_finite()).then_some(Fit {
+        shooter: (shooter.x, shooter.y, shooter.z),
+        yaw_offset,
+        residual,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn screen(monitor_yaw: f32) -> (ScreenFrame, Vec3) {
+        screen_plane(Orientation::Euler((monitor_yaw, 0.0, 0.0)), 0.0)
+    }
+
+    /* The Euler aim from shooter to a target in screen coordinates, with the screen facing monitor_yaw */
+    fn aim(shooter: ShooterCoord, target: PosCoord, monitor_yaw: f32) -> Orientation {
+        let (dx, dy, dz) = (target.0 - shooter.0, -shooter.1, target.1 - shooter.2);
+        let yaw = (monitor_yaw + dx.atan2(dy).to_degrees()).rem_euclid(360.0);
+        let pitch = -dz.atan2((dx * dx + dy * dy).sqrt()).to_degrees();
+        Orientation::Euler((yaw, pitch, 0.0))
+    }
+
+    fn grid(shooter: ShooterCoord, count: u8) -> InitData {
+        let window_size = (1600.0, 900.0);
+        let mut init_data = InitData::new(window_size);
+        init_data.set_monitor(aim(shooter, (0.0, 0.0), 37.0));
+        for (i, target) in grid_targets(count, window_size).into_iter().enumerate() {
+            init_data.set_sample(i, target, aim(shooter, target, 37.0));
+        }
+        init_data
+    }
+
+    #[test]
+    fn grid_samples_by_slot() {
+        let mut init_data = InitData::new((1600.0, 900.0));
+        init_data.set_sample(0, (0.0, 0.0), Orientation::default());
+        init_data.set_sample(0, (0.0, 0.0), Orientation::default());
+        init_data.set_sample(1, (10.0, 0.0), Orientation::default());
+        assert_eq!(init_data.samples().len(), 2);
+        init_data.set_sample(crate::client::init::MAX_SAMPLES, (0.0, 0.0), Orientation::default());
+        assert_eq!(init_data.samples().len(), 2);
+    }
+
+    #[test]
+    fn fit_recovers_shooter() {
+        for count in [5, 9] {
+            for shooter in [(0.0, -2000.0, 0.0), (150.0, -2000.0, 0.0), (0.0, -1500.0, 0.0)] {
+                let fit = fit(&grid(shooter, count)).unwrap();
+                assert!(fit.residual < 1.0, "{fit:?}");
+                assert!((fit.shooter.0 - shooter.0).abs() < 20.0 && (fit.shooter.1 - shooter.1).abs() < 20.0, "{fit:?} {shooter:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn fit_reports_shaky_clicks() {
+        let shooter = (0.0, -2000.0, 0.0);
+        let mut init_data = grid(shooter, 9);
+        let target = grid_targets(9, (1600.0, 900.0))[2];
+        let Orientation::Euler((yaw, pitch, roll)) = aim(shooter, target, 37.0) else { unreachable!() };
+        init_data.set_sample(2, target, Orientation::Euler((yaw + 0.5, pitch, roll)));
+        let fit = fit(&init_data).unwrap();
+        assert!(fit.residual > 1.0 && fit.residual < 20.0, "{fit:?}");
+    }
+
+    #[test]
+    fn fit_needs_three_samples() {
+        let mut init_data = grid((0.0, -2000.0, 0.0), 5);
+        init_data.set_monitor(Orientation::Euler((37.0, 0.0, 0.0)));
+        init_data.set_sample(0, (0.0, 0.0), Orientation::Euler((37.0, 0.0, 0.0)));
+        assert!(fit(&init_data).is_none());
+    }
+
+    #[test]
+    fn project_straight_ahead_hits_center() {
+        let (frame, normal) = screen(30.0);
+        let (x, y) = project(&frame, normal, Vec3::new(0.0, -2000.0, 0.0), Orientation::Euler((30.0, 0.0, 0.0)));
+        assert!(x.abs() < 1e-2 && y.abs() < 1e-2, "{x} {y}");
+    }
+
+    #[test]
+    fn project_away_from_screen_is_off_screen() {
+        let (frame, normal) = screen(30.0);
+        let shooter = Vec3::new(0.0, -2000.0, 0.0);
+        assert_eq!(project(&frame, normal, shooter, Orientation::Euler((210.0, 0.0, 0.0))), OFF_SCREEN);
+        assert_eq!(project(&frame, normal, shooter, Orientation::Euler((120.0, 0.0, 0.0))), OFF_SCREEN);
+        assert_eq!(project(&frame, normal, shooter, Orientation::Euler((30.0, 90.0, 0.0))), OFF_SCREEN);
+    }
+
+    #[test]
+    fn project_from_behind_the_screen_is_off_screen() {
+        let (frame, normal) = screen(30.0);
+        assert_eq!(project(&frame, normal, Vec3::new(0.0, 2000.0, 0.0), Orientation::Euler((30.0, 0.0, 0.0))), OFF_SCREEN);
+    }
+
+    fn side_points(first: Orientation, second: Orientation, monitor_yaw: f32) -> InitData {
+        let mut init_data = InitData::new((1600.0, 900.0));
+        init_data.set_monitor(Orientation::Euler((monitor_yaw, 0.0, 0.0)));
+        init_data.set_first_point(first);
+        init_data.set_second_point(second);
+        init_data
+    }
+
+    #[test]
+    fn sides_recover_shooter() {
+        for shooter in [(0.0, -2000.0, 0.0), (300.0, -1800.0, 200.0), (-250.0, -2500.0, -150.0)] {
+            let sides = sides(&side_points(aim(shooter, (-450.0, 0.0), 37.0), aim(shooter, (450.0, 0.0), 37.0), 37.0)).unwrap();
+            assert!(sides.is_consistent(), "{sides:?}");
+            assert!((sides.shooter.0 - shooter.0).abs() < 1.0 && (sides.shooter.1 - shooter.1).abs() < 1.0 && (sides.shooter.2 - shooter.2).abs() < 1.0, "{sides:?} {shooter:?}");
+        }
+    }
+
+    #[test]
+    fn sides_report_disagreeing_heights() {
+        let shooter = (0.0, -2000.0, 0.0);
+        let Orientation::Euler((yaw, pitch, roll)) = aim(shooter, (450.0, 0.0), 37.0) else { unreachable!() };
+        let sides = sides(&side_points(aim(shooter, (-450.0, 0.0), 37.0), Orientation::Euler((yaw, pitch + 5.0, roll)), 37.0)).unwrap();
+        assert!(!sides.is_consistent(), "{sides:?}");
+    }
+
+    #[test]
+    fn sides_need_aims_crossing_in_front() {
+        assert!(sides(&side_points(Orientation::Euler((57.0, 0.0, 0.0)), Orientation::Euler((17.0, 0.0, 0.0)), 37.0)).is_none());
+    }
+
+    #[test]
+    fn check_turns_down_degenerate_side_points() {
+        let shooter = (0.0, -1800.0, 0.0);
+        assert!(check(&side_points(aim(shooter, (-450.0, 0.0), 90.0), aim(shooter, (450.0, 0.0), 90.0), 90.0)).is_ok());
+        assert_eq!(check(&side_points(Orientation::Euler((90.5, 0.0, 0.0)), Orientation::Euler((90.0, 0.0, 0.0)), 90.0)).err(), Some(Problem::TooNarrow));
+        assert_eq!(check(&side_points(aim(shooter, (450.0, 0.0), 90.0), aim(shooter, (-450.0, 0.0), 90.0), 90.0)).err(), Some(Problem::NoCrossing));
+        assert_eq!(check(&side_points(Orientation::Euler((10.0, 0.0, 0.0)), Orientation::Euler((170.0, 0.0, 0.0)), 90.0)).err(), Some(Problem::TooClose));
+
+        let far = (0.0, -900.0 * 25.0, 0.0);
+        assert_eq!(check(&side_points(aim(far, (-450.0, 0.0), 90.0), aim(far, (450.0, 0.0), 90.0), 90.0)).err(), Some(Problem::TooFar));
+    }
+}
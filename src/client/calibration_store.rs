@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use crate::client::calibration::Fit;
+use crate::client::init::InitData;
+use crate::client::orientation::Orientation;
+use crate::client::ShooterCoord;
+
+/* The furniture moves now and then, a week old calibration is taken as a fresh start */
+pub const MAX_AGE: Duration = Duration::from_secs(7 * 24 * 60 * 60);
+
+/*
+    A finished calibration, and when it was taken
+ */
+#[derive(Copy, Clone, Debug)]
+pub struct Saved {
+    pub init_data: InitData,
+    pub shooter: ShooterCoord,
+    pub saved_at: u64,
+}
+
+impl Saved {
+    /* Any other window size puts the targets somewhere else, so the calibration doesn't carry over */
+    fn is_fresh(&self, window_size: (f32, f32)) -> bool {
+        self.init_data.window_size() == window_size && now().saturating_sub(self.saved_at) < MAX_AGE.as_secs()
+    }
+}
+
+/*
+    Calibrations by controller identity, the unique id it said hello with.
+    Without a path nothing is kept past the server's lifetime.
+ */
+#[derive(Clone, Default)]
+pub struct CalibrationStore {
+    path: Option<String>,
+    window_size: (f32, f32),
+    saved: Arc<Mutex<HashMap<String, Saved>>>,
+    /* Each save's snapshot is numbered, so a slow write never lands over a newer one */
+    generation: Arc<AtomicU64>,
+    written: Arc<Mutex<u64>>,
+}
+
+impl CalibrationStore {
+    /* A missing file is an empty store, it's created with the first calibration */
+    pub fn open(path: &str, window_size: (f32, f32)) -> CalibrationStore {
+        let mut saved = HashMap::new();
+        let mut stale = 0;
+        if let Ok(text) = std::fs::read_to_string(path) {
+            for line in text.lines().filter(|x| !x.trim().is_empty()) {
+                match parse_line(line) {
+                    Some((identity, x)) if x.is_fresh(window_size) => {
+                        saved.insert(identity, x);
+                    }
+                    Some(_) => stale += 1,
+                    None => println!("Skipping unreadable saved calibration \"{line}\""),
+                }
+            }
+        }
+        println!("Loaded {} saved calibrations from {path}, dropped {stale} stale ones", saved.len());
+
+        CalibrationStore {
+            path: Some(path.to_string()),
+            window_size,
+            saved: Arc::new(Mutex::new(saved)),
+            generation: Arc::new(AtomicU64::new(0)),
+            written: Arc::new(Mutex::new(0)),
+        }
+    }
+
+    pub fn get(&self, identity: &str) -> Option<Saved> {
+        self.saved.lock().unwrap().get(identity).copied().filter(|x| x.is_fresh(self.window_size))
+    }
+
+    /* The file is written off the async threads, from a snapshot taken under the lock */
+    pub fn save(&self, identity: &str, init_data: InitData, shooter: ShooterCoord) {
+        let (text, generation) = {
+            let mut saved = self.saved.lock().unwrap();
+            saved.insert(identity.to_string(), Saved { init_data, shooter, saved_at: now() });
+            let text: String = saved.iter().map(|(identity, x)| format_line(identity, x)).collect();
+            (text, self.generation.fetch_add(1, Ordering::Relaxed) + 1)
+        };
+
+        let Some(path) = self.path.clone() else {
+            return;
+        };
+        let written = self.written.clone();
+        tokio::task::spawn_blocking(move || {
+            let mut written = written.lock().unwrap();
+            if *written > generation {
+                return;
+            }
+            if let Err(e) = std::fs::write(&path, text) {
+                println!("Couldn't save calibrations to {path}: {e}");
+            }
+            *written = generation;
+        });
+    }
+}
+
+/*
+    One calibration per line, space separated:
+    saved at in unix seconds, window width and height, the monitor, first and second point,
+    the grid fit's yaw offset and residual or "-" for none, the shooter's x, y and h,
+    then the identity, which runs to the end of the line as ids can have spaces.
+    Orientations are "e:yaw,pitch,roll" or "q:w,x,y,z".
+ */
+fn format_line(identity: &str, saved: &Saved) -> String {
+    let init_data = &saved.init_data;
+    let (w, h) = init_data.window_size();
+    let fit = init_data.fit().map_or(String::from("-"), |x| format!("{},{}", x.yaw_offset, x.residual));
+    let (x, y, z) = saved.shooter;
+
+    format!(
+        "{} {w} {h} {} {} {} {fit} {x} {y} {z} {identity}\n",
+        saved.saved_at, format_orientation(init_data.monitor()), format_orientation(init_data.first_point()), format_orientation(init_data.second_point())
+    )
+}
+
+fn parse_line(line: &str) -> Option<(String, Saved)> {
+    let mut fields = line.splitn(11, ' ');
+    let mut next = || fields.next();
+
+    let saved_at = next()?.parse().ok()?;
+    let window_size = (next()?.parse().ok()?, next()?.parse().ok()?);
+    let monitor = parse_orientation(next()?)?;
+    let first_point = parse_orientation(next()?)?;
+    let second_point = parse_orientation(next()?)?;
+    let fit = next()?;
+    let shooter: ShooterCoord = (next()?.parse().ok()?, next()?.parse().ok()?, next()?.parse().ok()?);
+    let identity = next()?.to_string();
+
+    let fit = match fit {
+        "-" => None,
+        x => {
+            let (yaw_offset, residual) = x.split_once(',')?;
+            Some(Fit { shooter, yaw_offset: yaw_offset.parse().ok()?, residual: residual.parse().ok()? })
+        }
+    };
+
+    /* Stored points went through roll compensation already, and a new InitData has it off */
+    let mut init_data = InitData::new(window_size);
+    init_data.set_monitor(monitor);
+    init_data.set_first_point(first_point);
+    init_data.set_second_point(second_point);
+    init_data.set_fit(fit);
+
+    Some((identity, Saved { init_data, shooter, saved_at }))
+}
+
+fn format_orientation(orientation: Orientation) -> String {
+    match orientation {
+        Orientation::Euler((yaw, pitch, roll)) => format!("e:{yaw},{pitch},{roll}"),
+        Orientation::Quaternion(q) => format!("q:{},{},{},{}", q.w, q.x, q.y, q.z),
+    }
+}
+
+fn parse_orientation(text: &str) -> Option<Orientation> {
+    let (kind, values) = text.split_once(':')?;
+    let values: Vec<f32> = values.split(',').map(|x| x.parse().ok()).collect::<Option<_>>()?;
+
+    match (kind, values.as_slice()) {
+        ("e", [yaw, pitch, roll]) => Some(Orientation::Euler((*yaw, *pitch, *roll))),
+        ("q", [w, x, y, z]) => Some(Orientation::from_quaternion(*w, *x, *y, *z)),
+        _ => None,
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |x| x.as_secs())
+}
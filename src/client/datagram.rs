@@ -0,0 +1,193 @@
+use crate::client::mac::MAC_LEN;
+use crate::client::orientation::Orientation;
+use crate::client::SensorData;
+
+pub const LEGACY_LEN: usize = 12;
+pub const MAX_LEN: usize = 64;
+
+pub const ORIENTATION: i32 = 1;
+pub const SESSION_ORIENTATION: i32 = 2;
+pub const QUATERNION: i32 = 3;
+pub const SESSION_QUATERNION: i32 = 4;
+
+/*
+    Short is too few bytes for the type, Malformed an unknown type, any other wrong length,
+    or values that aren't finite
+ */
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum ParseError {
+    Short,
+    Malformed,
+}
+
+pub enum Datagram {
+    Legacy(SensorData),
+    Orientation {
+        token: Option<u32>,
+        seq: u32,
+        timestamp: u32,
+        data: Orientation,
+        /* Trailing MAC over everything before it, only session datagrams carry one */
+        mac: Option<[u8; MAC_LEN]>,
+    },
+}
+
+impl Datagram {
+    /*
+        Legacy: exactly 12 bytes of yaw, pitch, roll
+        Orientation: [0..4] type 1, [4..8] sequence number, [8..12] controller timestamp in ms,
+        [12..24] yaw, pitch, roll
+        Session orientation: [0..4] type 2, [4..8] session token from the handshake,
+        then the same layout as Orientation shifted by 4 bytes
+        Quaternion and session quaternion: types 3 and 4, laid out like the above
+        with a w, x, y, z unit quaternion in place of yaw, pitch, roll
+        Session datagrams of a controller that agreed to AUTH end in a MAC_LEN byte MAC, see mac.rs
+        Every type has its exact length, with or without the MAC, anything else is refused
+     */
+    pub fn parse(buf: &[u8]) -> Result<Datagram, ParseError> {
+        if buf.len() == LEGACY_LEN {
+            return finite(Datagram::Legacy(read_sensor_data(&buf[0..12])));
+        }
+
+        if buf.len() < 4 {
+            return Err(ParseError::Short);
+        }
+
+        let message_type = i32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let (len, session) = match message_type {
+            ORIENTATION => (24, false),
+            SESSION_ORIENTATION => (28, true),
+            QUATERNION => (28, false),
+            SESSION_QUATERNION => (32, true),
+            _ => return Err(ParseError::Malformed),
+        };
+
+        if buf.len() < len {
+            return Err(ParseError::Short);
+        }
+        let mac = if session && buf.len() == len + MAC_LEN {
+            buf[len..].try_into().ok()
+        } else if buf.len() == len {
+            None
+        } else {
+            return Err(ParseError::Malformed);
+        };
+
+        let (token, start) = if session { (Some(u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]])), 8) } else { (None, 4) };
+        finite(read_orientation(token, &buf[start..len], mac))
+    }
+
+    pub fn token(&self) -> Option<u32> {
+        match self {
+            Datagram::Legacy(_) => None,
+            Datagram::Orientation { token, .. } => *token,
+        }
+    }
+}
+
+fn read_orientation(token: Option<u32>, buf: &[u8], mac: Option<[u8; MAC_LEN]>) -> Datagram {
+    let seq = u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    let timestamp = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+
+    Datagram::Orientation {
+        token,
+        seq,
+        timestamp,
+        data: if buf.len() == 24 { read_quaternion(&buf[8..24]) } else { Orientation::Euler(read_sensor_data(&buf[8..20])) },
+        mac,
+    }
+}
+
+fn finite(datagram: Datagram) -> Result<Datagram, ParseError> {
+    let finite = match &datagram {
+        Datagram::Legacy(data) => Orientation::Euler(*data).is_finite(),
+        Datagram::Orientation { data, .. } => data.is_finite(),
+    };
+    if finite { Ok(datagram) } else { Err(ParseError::Malformed) }
+}
+
+fn read_quaternion(buf: &[u8]) -> Orientation {
+    let w = f32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    let x = f32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+    let y = f32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]);
+    let z = f32::from_be_bytes([buf[12], buf[13], buf[14], buf[15]]);
+
+    Orientation::from_quaternion(w, x, y, z)
+}
+
+fn read_sensor_data(buf: &[u8]) -> SensorData {
+    let y = f32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+    let p = f32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+    let r = f32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]);
+
+    (y, p, r)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bytes(message_type: i32, words: &[u32], values: &[f32]) -> Vec<u8> {
+        let mut buf = message_type.to_be_bytes().to_vec();
+        words.iter().for_each(|x| buf.extend(x.to_be_bytes()));
+        values.iter().for_each(|x| buf.extend(x.to_be_bytes()));
+        buf
+    }
+
+    fn euler(buf: &[u8]) -> (Option<u32>, u32, u32, SensorData, Option<[u8; MAC_LEN]>) {
+        match Datagram::parse(buf) {
+            Ok(Datagram::Orientation { token, seq, timestamp, data: Orientation::Euler(data), mac }) => (token, seq, timestamp, data, mac),
+            _ => panic!("not an euler orientation"),
+        }
+    }
+
+    #[test]
+    fn parses_legacy() {
+        let buf: Vec<u8> = [10.0f32, -20.0, 30.0].iter().flat_map(|x| x.to_be_bytes()).collect();
+        assert!(matches!(Datagram::parse(&buf), Ok(Datagram::Legacy((y, p, r))) if (y, p, r) == (10.0, -20.0, 30.0)));
+    }
+
+    #[test]
+    fn parses_each_type() {
+        assert_eq!(euler(&bytes(1, &[7, 500], &[1.0, 2.0, 3.0])), (None, 7, 500, (1.0, 2.0, 3.0), None));
+        assert_eq!(euler(&bytes(2, &[99, 7, 500], &[1.0, 2.0, 3.0])), (Some(99), 7, 500, (1.0, 2.0, 3.0), None));
+
+        for (message_type, words) in [(3, &[7u32, 500][..]), (4, &[99, 7, 500][..])] {
+            let Ok(Datagram::Orientation { token, seq, data: Orientation::Quaternion(q), .. }) = Datagram::parse(&bytes(message_type, words, &[0.0, 0.0, 0.0, 2.0])) else {
+                panic!("type {message_type} is not a quaternion");
+            };
+            assert_eq!((token, seq), (if message_type == 4 { Some(99) } else { None }, 7));
+            assert_eq!(q.z, 1.0);
+        }
+    }
+
+    #[test]
+    fn keeps_the_trailing_mac_of_session_datagrams() {
+        let mut buf = bytes(2, &[99, 7, 500], &[1.0, 2.0, 3.0]);
+        buf.extend([1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(euler(&buf).4, Some([1, 2, 3, 4, 5, 6, 7, 8]));
+
+        let mut buf = bytes(1, &[7, 500], &[1.0, 2.0, 3.0]);
+        buf.extend([1, 2, 3, 4, 5, 6, 7, 8]);
+        assert_eq!(Datagram::parse(&buf).err(), Some(ParseError::Malformed));
+    }
+
+    #[test]
+    fn refuses_wrong_lengths() {
+        assert_eq!(Datagram::parse(&[]).err(), Some(ParseError::Short));
+        assert_eq!(Datagram::parse(&[0, 0, 0]).err(), Some(ParseError::Short));
+        assert_eq!(Datagram::parse(&bytes(1, &[7, 500], &[1.0, 2.0])).err(), Some(ParseError::Short));
+        assert_eq!(Datagram::parse(&bytes(4, &[99, 7, 500], &[1.0, 0.0, 0.0])).err(), Some(ParseError::Short));
+        assert_eq!(Datagram::parse(&bytes(1, &[7, 500], &[1.0, 2.0, 3.0, 4.0])).err(), Some(ParseError::Malformed));
+        assert_eq!(Datagram::parse(&bytes(2, &[99, 7, 500], &[1.0, 2.0, 3.0, 4.0])).err(), Some(ParseError::Malformed));
+        assert_eq!(Datagram::parse(&bytes(5, &[7, 500], &[1.0, 2.0, 3.0])).err(), Some(ParseError::Malformed));
+    }
+
+    #[test]
+    fn refuses_values_that_are_not_finite() {
+        let buf: Vec<u8> = [f32::NAN, 0.0, 0.0].iter().flat_map(|x| x.to_be_bytes()).collect();
+        assert_eq!(Datagram::parse(&buf).err(), Some(ParseError::Malformed));
+        assert_eq!(Datagram::parse(&bytes(1, &[7, 500], &[0.0, f32::INFINITY, 0.0])).err(), Some(ParseError::Malformed));
+        assert_eq!(Datagram::parse(&bytes(3, &[7, 500], &[0.0, 0.0, 0.0, 0.0])).err(), Some(ParseError::Malformed));
+    }
+}
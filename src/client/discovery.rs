@@ -0,0 +1,107 @@
+use std::net::SocketAddr;
+use std::time::Duration;
+use tokio::net::UdpSocket;
+use tokio::sync::watch;
+use crate::client::handshake::PROTOCOL_VERSION;
+use crate::client::rate_limit::RateLimiter;
+use crate::client::session::{MAX_PLAYERS, Slots};
+
+pub const DISCOVERY_PORT: u16 = 11077;
+pub const PROBE: i32 = 20;
+pub const ANNOUNCE: i32 = 21;
+
+const ANNOUNCE_INTERVAL: Duration = Duration::from_secs(2);
+const MAX_NAME_LEN: usize = 32;
+/*
+    Probes a second any one IP gets answered, and how many at once. A controller probes
+    now and then while it looks for a server, anything more is someone using us as a reflector.
+ */
+const PROBE_RATE: f32 = 1.0;
+const PROBE_BURST: f32 = 5.0;
+
+/*
+    Lets controllers find the server without being told its address. A probe is a datagram
+    to DISCOVERY_PORT starting with type 20, answered straight back to its sender. The same
+    announcement also goes out to the limited broadcast address now and then, which reaches
+    the local subnet whatever its range, so controllers can just listen instead of probing.
+    Answers are rate limited per source, as they're bigger than the probes asking for them.
+
+    Announcement (type 21) layout, after the 4 byte message type:
+    [4..6] protocol version, [6..8] controller TCP port, [8..10] browser controller port,
+    [10] free player slots, [11] max players, [12] 1 if the lobby is open,
+    [13] 1 if joining needs the pairing PIN, [14..16] name length, followed by the UTF-8 server name
+ */
+pub struct Discovery {
+    pub name: String,
+    pub tcp_port: u16,
+    pub web_port: u16,
+    pub slots: Slots,
+    pub pairing: bool,
+    pub lobby_open_rx: watch::Receiver<bool>,
+}
+
+impl Discovery {
+    pub async fn run(self, bind_addr: &str) {
+        let sock = match UdpSocket::bind(bind_addr).await {
+            Ok(sock) => sock,
+            Err(e) => {
+                println!("Discovery disabled, can't bind {bind_addr}: {e}");
+                return;
+            }
+        };
+        if let Err(e) = sock.set_broadcast(true) {
+            println!("Discovery won't broadcast announcements: {e}");
+        }
+        println!("Answering discovery probes on {} as \"{}\"", sock.local_addr().unwrap(), self.name);
+
+        let broadcast_addr = SocketAddr::from(([255, 255, 255, 255], DISCOVERY_PORT));
+        let mut announce = tokio::time::interval(ANNOUNCE_INTERVAL);
+        let mut buf = [0u8; 64];
+        let mut rate_limiter = RateLimiter::new(PROBE_RATE, PROBE_BURST, "discovery probes");
+
+        loop {
+            tokio::select! {
+                _ = announce.tick() => {
+                    sock.send_to(&self.announcement(), broadcast_addr).await.ok();
+                }
+                received = sock.recv_from(&mut buf) => {
+                    let Ok((n, addr)) = received else {
+                        continue;
+                    };
+                    if n < 4 || i32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) != PROBE {
+                        continue;
+                    }
+                    /* Logged once per source rather than per probe, so probes can't flood the log either */
+                    let first = !rate_limiter.is_known(addr.ip());
+                    if !rate_limiter.allow(addr.ip()) {
+                        continue;
+                    }
+                    if first {
+                        println!("Answering discovery probes from {}", addr.ip());
+                    }
+                    sock.send_to(&self.announcement(), addr).await.ok();
+                }
+            }
+        }
+    }
+
+    fn announcement(&self) -> Vec<u8> {
+        let lobby_open = *self.lobby_open_rx.borrow();
+        /* Nobody can join mid-match, so there are no free slots to advertise until the lobby opens */
+        let free = if lobby_open { self.slots.free() } else { 0 };
+        let name: String = self.name.chars().take(MAX_NAME_LEN).collect();
+
+        let mut buf = vec![];
+        buf.extend(ANNOUNCE.to_be_bytes());
+        buf.extend(PROTOCOL_VERSION.to_be_bytes());
+        buf.extend(self.tcp_port.to_be_bytes());
+        buf.extend(self.web_port.to_be_bytes());
+        buf.push(free as u8);
+        buf.push(MAX_PLAYERS as u8);
+        buf.push(lobby_open as u8);
+        buf.push(self.pairing as u8);
+        buf.extend((name.len() as u16).to_be_bytes());
+        buf.extend(name.as_bytes());
+        buf
+    }
+}
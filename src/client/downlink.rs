@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use macroquad::color::Color;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+use tokio::sync::mpsc;
+use crate::client::init::InitPhase;
+
+pub type DownlinkSender = mpsc::UnboundedSender<(u32, Downlink)>;
+
+pub const HIT_CONFIRMED: i32 = 110;
+pub const POINTS_GAINED: i32 = 111;
+pub const PHASE_CHANGED: i32 = 112;
+pub const VIBRATE: i32 = 113;
+pub const LED_COLOR: i32 = 114;
+pub const PING: i32 = 115;
+
+/* Phase codes a PhaseChanged carries in [4..8] */
+pub const PHASE_WAIT_MONITOR: u32 = 0;
+pub const PHASE_WAIT_FIRST_POINT: u32 = 1;
+pub const PHASE_WAIT_SECOND_POINT: u32 = 2;
+pub const PHASE_FINALIZE: u32 = 3;
+pub const PHASE_GAME: u32 = 4;
+pub const PHASE_RESULTS: u32 = 5;
+pub const PHASE_LOBBY: u32 = 6;
+pub const PHASE_WAIT_GRID_POINT: u32 = 7;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Phase {
+    Lobby,
+    Init(InitPhase),
+    Game,
+    Results,
+}
+
+impl Phase {
+    fn code(&self) -> u32 {
+        match self {
+            Phase::Init(InitPhase::WaitMonitor) => PHASE_WAIT_MONITOR,
+            Phase::Init(InitPhase::WaitFirstPoint) => PHASE_WAIT_FIRST_POINT,
+            Phase::Init(InitPhase::WaitSecondPoint) => PHASE_WAIT_SECOND_POINT,
+            Phase::Init(InitPhase::Finalize) => PHASE_FINALIZE,
+            Phase::Game => PHASE_GAME,
+            Phase::Results => PHASE_RESULTS,
+            Phase::Lobby => PHASE_LOBBY,
+            Phase::Init(InitPhase::WaitGridPoint { .. }) => PHASE_WAIT_GRID_POINT,
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Downlink {
+    HitConfirmed,
+    PointsGained(i32),
+    PhaseChanged(Phase),
+    Vibrate(u32),
+    LedColor(u8, u8, u8),
+    /* Controllers answer with a Pong carrying the same id */
+    Ping(u32),
+}
+
+impl Downlink {
+    pub fn led_color(color: Color) -> Downlink {
+        let [r, g, b, _] = color.into();
+        Downlink::LedColor(r, g, b)
+    }
+
+    /*
+        Same 16 byte framing as the uplink, message type in [0..4]
+        and the payload right after it
+     */
+    pub async fn write<W: AsyncWrite + Unpin>(&self, socket: &mut W) -> std::io::Result<()> {
+        let mut buf = [0u8; 16];
+
+        let message_type: i32 = match self {
+            Downlink::HitConfirmed => HIT_CONFIRMED,
+            Downlink::PointsGained(points) => {
+                buf[4..8].copy_from_slice(&points.to_be_bytes());
+                POINTS_GAINED
+            }
+            /* Grid targets add [8..12] which one and [12..16] how many, see calibration.rs for where they are */
+            Downlink::PhaseChanged(phase) => {
+                buf[4..8].copy_from_slice(&phase.code().to_be_bytes());
+                if let Phase::Init(InitPhase::WaitGridPoint { index, count }) = phase {
+                    buf[8..12].copy_from_slice(&(*index as u32).to_be_bytes());
+                    buf[12..16].copy_from_slice(&(*count as u32).to_be_bytes());
+                }
+                PHASE_CHANGED
+            }
+            Downlink::Vibrate(ms) => {
+                buf[4..8].copy_from_slice(&ms.to_be_bytes());
+                VIBRATE
+            }
+            Downlink::LedColor(r, g, b) => {
+                buf[4] = *r;
+                buf[5] = *g;
+                buf[6] = *b;
+                LED_COLOR
+            }
+            Downlink::Ping(id) => {
+                buf[4..8].copy_from_slice(&id.to_be_bytes());
+                PING
+            }
+        };
+        buf[0..4].copy_from_slice(&message_type.to_be_bytes());
+
+        socket.write_all(&buf).await
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct DownlinkRoutes {
+    client_txs: Arc<Mutex<HashMap<u32, mpsc::UnboundedSender<Downlink>>>>,
+}
+
+impl DownlinkRoutes {
+    pub fn insert(&self, client: u32, tx: mpsc::UnboundedSender<Downlink>) {
+        self.client_txs.lock().unwrap().insert(client, tx);
+    }
+
+    pub fn remove(&self, client: u32) {
+        self.client_txs.lock().unwrap().remove(&client);
+    }
+}
+
+pub async fn route(mut downlink_rx: mpsc::UnboundedReceiver<(u32, Downlink)>, routes: DownlinkRoutes) {
+    while let Some((client, message)) = downlink_rx.recv().await {
+        if let Some(tx) = routes.client_txs.lock().unwrap().get(&client) {
+            tx.send(message).ok();
+        }
+    }
+}
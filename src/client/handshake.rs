@@ -0,0 +1,193 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use crate::client::mac::Key;
+use crate::client::raw_message::DecodeError;
+
+pub const PROTOCOL_VERSION: u16 = 3;
+pub const MIN_PROTOCOL_VERSION: u16 = 1;
+pub const SERVER_CAPABILITIES: Capabilities = Capabilities::from_bits(Capabilities::QUATERNION | Capabilities::HAPTICS | Capabilities::AUTH | Capabilities::ROLL);
+
+pub const ACCEPT: i32 = 100;
+pub const REJECT: i32 = 101;
+
+const MAX_NAME_LEN: usize = 32;
+const MAX_ID_LEN: usize = 64;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct Capabilities(u16);
+
+impl Capabilities {
+    pub const QUATERNION: u16 = 1 << 0;
+    pub const HAPTICS: u16 = 1 << 1;
+    pub const BATTERY: u16 = 1 << 2;
+    /* Session datagrams carry a MAC under a key the accept hands out */
+    pub const AUTH: u16 = 1 << 3;
+    /* Yaw and pitch are measured in the gun's frame and get turned back by the roll, see Orientation::unrolled */
+    pub const ROLL: u16 = 1 << 4;
+
+    pub const fn empty() -> Self {
+        Capabilities(0)
+    }
+
+    pub const fn from_bits(bits: u16) -> Self {
+        Capabilities(bits)
+    }
+
+    pub fn bits(&self) -> u16 {
+        self.0
+    }
+
+    pub fn has(&self, flag: u16) -> bool {
+        self.0 & flag == flag
+    }
+
+    pub fn intersect(&self, other: Capabilities) -> Capabilities {
+        Capabilities(self.0 & other.0)
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Claim {
+    Index(u32),
+    Session(u32),
+}
+
+/*
+    Hello (type 4) frame layout, after the 4 byte message type:
+    [4..6] protocol version, [6..8] capability bits, [8..12] requested index,
+    [12..14] controller model, [14..16] name length, followed by the UTF-8 name
+    Resume (type 5) is the same, with the session token from an earlier Accept in [8..12]
+    From v2 on the name is followed by a 4 byte pairing PIN, 0 if the controller has none
+    From v3 on the PIN is followed by [2] id length and the controller's own unique id,
+    like a serial number or MAC address, empty if it has none
+ */
+#[derive(Clone, Debug)]
+pub struct Handshake {
+    version: u16,
+    capabilities: Capabilities,
+    claim: Claim,
+    model: u16,
+    name: String,
+    pin: Option<u32>,
+    id: Option<String>,
+}
+
+impl Handshake {
+    pub(super) async fn read_rest<R: AsyncRead + Unpin>(buf: &[u8], socket: &mut R, resume: bool) -> Result<Handshake, DecodeError> {
+        let version = u16::from_be_bytes([buf[4], buf[5]]);
+        let capabilities = Capabilities::from_bits(u16::from_be_bytes([buf[6], buf[7]]));
+        let claimed = u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]);
+        let claim = if resume { Claim::Session(claimed) } else { Claim::Index(claimed) };
+        let model = u16::from_be_bytes([buf[12], buf[13]]);
+        let name_len = u16::from_be_bytes([buf[14], buf[15]]) as usize;
+
+        let mut name = vec![0u8; name_len];
+        socket.read_exact(&mut name).await?;
+        let name: String = String::from_utf8_lossy(&name).chars().take(MAX_NAME_LEN).collect();
+
+        let mut pin = None;
+        if version >= 2 {
+            let mut buf = [0u8; 4];
+            socket.read_exact(&mut buf).await?;
+            pin = Some(u32::from_be_bytes(buf)).filter(|x| *x != 0);
+        }
+
+        let mut id = None;
+        if version >= 3 {
+            let mut buf = [0u8; 2];
+            socket.read_exact(&mut buf).await?;
+            let mut bytes = vec![0u8; u16::from_be_bytes(buf) as usize];
+            socket.read_exact(&mut bytes).await?;
+            /* It ends up in the calibrations file, one per line */
+            let text: String = String::from_utf8_lossy(&bytes).chars().filter(|x| !x.is_control()).take(MAX_ID_LEN).collect();
+            id = Some(text).filter(|x| !x.trim().is_empty());
+        }
+
+        Ok(Handshake {
+            version,
+            capabilities,
+            claim,
+            model,
+            name,
+            pin,
+            id,
+        })
+    }
+
+    pub fn version(&self) -> u16 {
+        self.version
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    pub fn claim(&self) -> Claim {
+        self.claim
+    }
+
+    pub fn model(&self) -> u16 {
+        self.model
+    }
+
+    pub fn name(&self) -> &str {
+        self.name.as_str()
+    }
+
+    pub fn pin(&self) -> Option<u32> {
+        self.pin
+    }
+
+    pub fn id(&self) -> Option<&str> {
+        self.id.as_deref()
+    }
+
+    pub fn is_version_supported(&self) -> bool {
+        MIN_PROTOCOL_VERSION <= self.version && self.version <= PROTOCOL_VERSION
+    }
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum RejectReason {
+    UnsupportedVersion = 1,
+    NoSuchSession = 2,
+    MatchInProgress = 3,
+    LobbyFull = 4,
+    WrongPin = 5,
+    TooManyAttempts = 6,
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum HandshakeReply {
+    Accept { index: u32, capabilities: Capabilities, session: u32, key: Option<Key> },
+    Reject(RejectReason),
+}
+
+impl HandshakeReply {
+    /*
+        Accept: [0..4] 100, [4..6] server protocol version, [6..8] agreed capability bits, [8..12] assigned index,
+        [12..16] session token to put in UDP datagrams, and with AUTH agreed [16..32] the key to sign them with
+        Reject: [0..4] 101, [4..6] server protocol version, [8..12] reason
+     */
+    pub async fn write<W: AsyncWrite + Unpin>(&self, socket: &mut W) -> std::io::Result<()> {
+        let mut buf = vec![0u8; 16];
+        buf[4..6].copy_from_slice(&PROTOCOL_VERSION.to_be_bytes());
+
+        match self {
+            HandshakeReply::Accept { index, capabilities, session, key } => {
+                buf[0..4].copy_from_slice(&ACCEPT.to_be_bytes());
+                buf[6..8].copy_from_slice(&capabilities.bits().to_be_bytes());
+                buf[8..12].copy_from_slice(&index.to_be_bytes());
+                buf[12..16].copy_from_slice(&session.to_be_bytes());
+                if let Some(key) = key {
+                    buf.extend(key);
+                }
+            }
+            HandshakeReply::Reject(reason) => {
+                buf[0..4].copy_from_slice(&REJECT.to_be_bytes());
+                buf[8..12].copy_from_slice(&(*reason as u32).to_be_bytes());
+            }
+        }
+
+        socket.write_all(&buf).await
+    }
+}
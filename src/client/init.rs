@@ -1,28 +1,43 @@
-use crate::client::SensorData;
+use crate::client::calibration::Fit;
+use crate::client::orientation::Orientation;
+use crate::client::PosCoord;
+
+pub const MAX_SAMPLES: usize = 9;
 
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub enum InitPhase {
     WaitMonitor,
     WaitFirstPoint,
     WaitSecondPoint,
+    /* Grid calibration, aiming at target index of count, see calibration.rs */
+    WaitGridPoint { index: u8, count: u8 },
     Finalize,
 }
 
 #[derive(Copy, Clone, Debug)]
 pub struct InitData {
     window_size: (f32, f32),
-    monitor: SensorData,
-    first_point: SensorData,
-    second_point: SensorData,
+    monitor: Orientation,
+    first_point: Orientation,
+    second_point: Orientation,
+    /* Grid targets and the aim that hit each, by the target's index */
+    samples: [(PosCoord, Orientation); MAX_SAMPLES],
+    sample_count: usize,
+    fit: Option<Fit>,
+    roll_compensation: bool,
 }
 
 impl InitData {
     pub fn new(window_size: (f32, f32)) -> InitData {
         InitData {
             window_size,
-            monitor: (0.0, 0.0, 0.0),
-            first_point: (0.0, 0.0, 0.0),
-            second_point: (0.0, 0.0, 0.0),
+            monitor: Orientation::default(),
+            first_point: Orientation::default(),
+            second_point: Orientation::default(),
+            samples: [((0.0, 0.0), Orientation::default()); MAX_SAMPLES],
+            sample_count: 0,
+            fit: None,
+            roll_compensation: false,
         }
     }
 
@@ -30,27 +45,64 @@ impl InitData {
         self.window_size
     }
 
-    pub fn monitor(&self) -> SensorData {
+    pub fn monitor(&self) -> Orientation {
         self.monitor
     }
 
-    pub fn first_point(&self) -> SensorData {
+    pub fn first_point(&self) -> Orientation {
         self.first_point
     }
 
-    pub fn second_point(&self) -> SensorData {
+    pub fn second_point(&self) -> Orientation {
         self.second_point
     }
+
+    pub fn samples(&self) -> &[(PosCoord, Orientation)] {
+        &self.samples[..self.sample_count]
+    }
+
+    /* Set by grid calibration, the three point flow leaves it at None */
+    pub fn fit(&self) -> Option<Fit> {
+        self.fit
+    }
+
+    pub fn yaw_offset(&self) -> f32 {
+        self.fit.map_or(0.0, |x| x.yaw_offset)
+    }
     
-    pub fn set_monitor(&mut self, data: SensorData) {
+    /* Every calibration starts here, so this also drops what an earlier grid left behind */
+    pub fn set_monitor(&mut self, data: Orientation) {
         self.monitor = data;
+        self.sample_count = 0;
+        self.fit = None;
     }
     
-    pub fn set_first_point(&mut self, data: SensorData) {
-        self.first_point = data;
+    pub fn set_first_point(&mut self, data: Orientation) {
+        self.first_point = self.aim(data);
     }
     
-    pub fn set_second_point(&mut self, data: SensorData) {
-        self.second_point = data;
+    pub fn set_second_point(&mut self, data: Orientation) {
+        self.second_point = self.aim(data);
     }
-}
\ No newline at end of file
+
+    /* Grid points come in order, so the samples so far run up to the latest index */
+    pub fn set_sample(&mut self, index: usize, target: PosCoord, data: Orientation) {
+        if index < MAX_SAMPLES {
+            self.samples[index] = (target, self.aim(data));
+            self.sample_count = self.sample_count.max(index + 1);
+        }
+    }
+
+    pub fn set_fit(&mut self, fit: Option<Fit>) {
+        self.fit = fit;
+    }
+
+    pub fn set_roll_compensation(&mut self, on: bool) {
+        self.roll_compensation = on;
+    }
+
+    /* Where the barrel points, with roll compensated around the monitor sample when the controller asked for it */
+    pub fn aim(&self, data: Orientation) -> Orientation {
+        if self.roll_compensation { data.unrolled(self.monitor) } else { data }
+    }
+}
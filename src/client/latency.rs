@@ -0,0 +1,120 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use crate::client::position_manager::StreamStats;
+
+pub const PING_INTERVAL: Duration = Duration::from_secs(1);
+pub const LOG_INTERVAL: Duration = Duration::from_secs(30);
+/* A sync sample older than this gives way to any newer one, so clock drift gets followed */
+const SYNC_MAX_AGE: Duration = Duration::from_secs(20);
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct LatencyStats {
+    /* Smoothed TCP round trip and its mean deviation, the same estimator TCP uses for retransmits */
+    pub rtt: Option<Duration>,
+    pub rtt_jitter: Duration,
+    /* One-way UDP delay: half the round trip, plus how far transit time sits above the best seen */
+    pub udp_delay: Option<Duration>,
+    pub udp_jitter: Duration,
+}
+
+impl LatencyStats {
+    pub(super) fn add_rtt(&mut self, sample: Duration) {
+        match self.rtt {
+            None => {
+                self.rtt = Some(sample);
+                self.rtt_jitter = sample / 2;
+            }
+            Some(rtt) => {
+                let deviation = sample.abs_diff(rtt);
+                self.rtt_jitter = (self.rtt_jitter * 3 + deviation) / 4;
+                self.rtt = Some((rtt * 7 + sample) / 8);
+            }
+        }
+    }
+
+    pub(super) fn set_udp(&mut self, stream: &StreamStats) {
+        if stream.last_seq.is_none() {
+            return;
+        }
+        let queueing = Duration::from_secs_f32(stream.queueing_ms.max(0.0) / 1000.0);
+        self.udp_delay = self.rtt.map(|x| x / 2 + queueing);
+        self.udp_jitter = Duration::from_secs_f32(stream.jitter_ms.max(0.0) / 1000.0);
+    }
+}
+
+/*
+    Maps controller timestamps onto server time. A pong's timestamp was taken about half a round
+    trip after the ping left, so each pong gives the offset between the two clocks, and the one
+    with the tightest round trip gives it best.
+ */
+pub(super) struct ClockSync {
+    epoch: Instant,
+    /* Server ms since epoch minus controller ms, the round trip it was measured over, and when */
+    offset: Option<(u32, Duration, Instant)>,
+}
+
+impl ClockSync {
+    pub(super) fn new() -> Self {
+        Self {
+            epoch: Instant::now(),
+            offset: None,
+        }
+    }
+
+    pub(super) fn add(&mut self, sent: Instant, rtt: Duration, timestamp: u32) {
+        if let Some((_, best_rtt, taken)) = self.offset {
+            if rtt > best_rtt && taken.elapsed() < SYNC_MAX_AGE {
+                return;
+            }
+        }
+        let server_ms = (sent + rtt / 2 - self.epoch).as_millis() as u32;
+        self.offset = Some((server_ms.wrapping_sub(timestamp), rtt, Instant::now()));
+    }
+
+    /* When a controller timestamp happened on the server's clock, None until the first pong */
+    pub(super) fn to_server(&self, timestamp: u32) -> Option<Instant> {
+        let (offset, _, _) = self.offset?;
+        let now = Instant::now();
+        let now_ms = (now - self.epoch).as_millis() as u32;
+        let ago = now_ms.wrapping_sub(timestamp.wrapping_add(offset)) as i32;
+        /* Slightly in the future is sync error, leave it at now */
+        now.checked_sub(Duration::from_millis(ago.max(0) as u64))
+    }
+}
+
+/*
+    Latency of every connected player by index, for the debug overlay and the logs
+ */
+#[derive(Clone)]
+pub struct Latency {
+    stats_tx: Arc<watch::Sender<BTreeMap<u32, LatencyStats>>>,
+}
+
+impl Latency {
+    pub fn new() -> Self {
+        let (stats_tx, _) = watch::channel(BTreeMap::new());
+        Self {
+            stats_tx: Arc::new(stats_tx),
+        }
+    }
+
+    pub fn stats(&self) -> watch::Receiver<BTreeMap<u32, LatencyStats>> {
+        self.stats_tx.subscribe()
+    }
+
+    pub(super) fn update(&self, index: u32, stats: LatencyStats) {
+        self.stats_tx.send_modify(|x| { x.insert(index, stats); });
+    }
+
+    pub(super) fn forget(&self, index: u32) {
+        self.stats_tx.send_if_modified(|x| x.remove(&index).is_some());
+    }
+}
+
+impl Default for Latency {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,54 @@
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::watch;
+
+/* Silence longer than this marks a player as stale on screen */
+pub const STALE_AFTER: Duration = Duration::from_secs(2);
+pub const HEARTBEAT_INTERVAL: Duration = Duration::from_millis(500);
+
+/*
+    Tracks which players went silent on both TCP and UDP.
+    A player silent for the whole grace period gets its connection dropped and is parked
+    like any other disconnect, so nothing waits on it forever.
+ */
+#[derive(Clone)]
+pub struct Liveness {
+    stale_tx: Arc<watch::Sender<BTreeSet<u32>>>,
+    grace_period: Duration,
+}
+
+impl Liveness {
+    pub fn new(grace_period: Duration) -> Self {
+        let (stale_tx, _) = watch::channel(BTreeSet::new());
+        Self {
+            stale_tx: Arc::new(stale_tx),
+            grace_period,
+        }
+    }
+
+    pub fn stale(&self) -> watch::Receiver<BTreeSet<u32>> {
+        self.stale_tx.subscribe()
+    }
+
+    pub fn grace_period(&self) -> Duration {
+        self.grace_period
+    }
+
+    pub(super) fn set_stale(&self, index: u32, stale: bool) {
+        self.stale_tx.send_if_modified(|x| {
+            let changed = if stale { x.insert(index) } else { x.remove(&index) };
+            if changed && stale {
+                println!("Client {index} went silent, marking as stale");
+            } else if changed {
+                println!("Client {index} is responsive again");
+            }
+            changed
+        });
+    }
+
+    /* Disconnected players aren't stale, just gone */
+    pub(super) fn forget(&self, index: u32) {
+        self.stale_tx.send_if_modified(|x| x.remove(&index));
+    }
+}
@@ -0,0 +1,149 @@
+pub const KEY_LEN: usize = 16;
+pub const MAC_LEN: usize = 8;
+
+pub type Key = [u8; KEY_LEN];
+
+/*
+    Session keys authenticate orientation datagrams. They travel in the TCP accept,
+    so only whoever holds the controller's connection can sign for it.
+ */
+pub fn random_key() -> Key {
+    rand::random()
+}
+
+/*
+    HMAC-SHA1 cut down to its first MAC_LEN bytes, which is plenty for a
+    packet that's worthless a few frames after it was sent
+ */
+pub fn sign(key: &Key, data: &[u8]) -> [u8; MAC_LEN] {
+    let mut ret = [0u8; MAC_LEN];
+    ret.copy_from_slice(&hmac_sha1(key, data)[..MAC_LEN]);
+    ret
+}
+
+/* Looks at every byte whatever the first mismatch, so timing tells nothing about the MAC */
+pub fn verify(key: &Key, data: &[u8], mac: &[u8]) -> bool {
+    mac.len() == MAC_LEN && sign(key, data).iter().zip(mac).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/* RFC 2104, keys longer than a block are hashed down first */
+fn hmac_sha1(key: &[u8], data: &[u8]) -> [u8; 20] {
+    let hashed;
+    let key = if key.len() > 64 {
+        hashed = sha1(key);
+        &hashed[..]
+    } else {
+        key
+    };
+
+    let mut inner_pad = [0x36u8; 64];
+    let mut outer_pad = [0x5cu8; 64];
+    for (i, x) in key.iter().enumerate() {
+        inner_pad[i] ^= x;
+        outer_pad[i] ^= x;
+    }
+
+    let mut inner = inner_pad.to_vec();
+    inner.extend(data);
+    let mut outer = outer_pad.to_vec();
+    outer.extend(sha1(&inner));
+    sha1(&outer)
+}
+
+pub(crate) fn sha1(data: &[u8]) -> [u8; 20] {
+    let mut h: [u32; 5] = [0x67452301, 0xEFCDAB89, 0x98BADCFE, 0x10325476, 0xC3D2E1F0];
+
+    let mut message = data.to_vec();
+    message.push(0x80);
+    while message.len() % 64 != 56 {
+        message.push(0);
+    }
+    message.extend((data.len() as u64 * 8).to_be_bytes());
+
+    for chunk in message.chunks(64) {
+        let mut w = [0u32; 80];
+        for i in 0..16 {
+            w[i] = u32::from_be_bytes([chunk[i * 4], chunk[i * 4 + 1], chunk[i * 4 + 2], chunk[i * 4 + 3]]);
+        }
+        for i in 16..80 {
+            w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e] = h;
+        for (i, x) in w.iter().enumerate() {
+            let (f, k) = match i {
+                0..=19 => ((b & c) | (!b & d), 0x5A827999),
+                20..=39 => (b ^ c ^ d, 0x6ED9EBA1),
+                40..=59 => ((b & c) | (b & d) | (c & d), 0x8F1BBCDC),
+                _ => (b ^ c ^ d, 0xCA62C1D6),
+            };
+            let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(*x);
+            e = d;
+            d = c;
+            c = b.rotate_left(30);
+            b = a;
+            a = temp;
+        }
+
+        for (x, y) in h.iter_mut().zip([a, b, c, d, e]) {
+            *x = x.wrapping_add(y);
+        }
+    }
+
+    let mut ret = [0u8; 20];
+    for (i, x) in h.iter().enumerate() {
+        ret[i * 4..i * 4 + 4].copy_from_slice(&x.to_be_bytes());
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(bytes: &[u8]) -> String {
+        bytes.iter().map(|x| format!("{x:02x}")).collect()
+    }
+
+    /* RFC 3174 section 7.3, plus the empty message */
+    #[test]
+    fn sha1_known_answers() {
+        assert_eq!(hex(&sha1(b"")), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(hex(&sha1(b"abc")), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(hex(&sha1(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq")), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+        assert_eq!(hex(&sha1(&[b'a'; 1_000_000])), "34aa973cd4c4daa4f61eeb2bdbad27316534016f");
+        assert_eq!(hex(&sha1(&b"01234567".repeat(80))), "dea356a2cddd90c7a7ecedc5ebb563934f460452");
+    }
+
+    /* RFC 2202 section 3 */
+    #[test]
+    fn hmac_sha1_known_answers() {
+        let cases: [(&[u8], &[u8], &str); 7] = [
+            (&[0x0b; 20], b"Hi There", "b617318655057264e28bc0b6fb378c8ef146be00"),
+            (b"Jefe", b"what do ya want for nothing?", "effcdf6ae5eb2fa2d27416d5f184df9c259a7c79"),
+            (&[0xaa; 20], &[0xdd; 50], "125d7342b9ac11cd91a39af48aa17b4f63f175d3"),
+            (&[1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25], &[0xcd; 50], "4c9007f4026250c6bc8414f9bf50c86c2d7235da"),
+            (&[0x0c; 20], b"Test With Truncation", "4c1a03424b55e07fe7f27be1d58bb9324a9a5a04"),
+            (&[0xaa; 80], b"Test Using Larger Than Block-Size Key - Hash Key First", "aa4ae5e15272d00e95705637ce8a3b55ed402112"),
+            (&[0xaa; 80], b"Test Using Larger Than Block-Size Key and Larger Than One Block-Size Data", "e8e99d0f45237d786d6bbaa7965c7808bbff1a91"),
+        ];
+        for (key, data, expected) in cases {
+            assert_eq!(hex(&hmac_sha1(key, data)), expected);
+        }
+    }
+
+    #[test]
+    fn signs_with_the_truncated_hmac() {
+        let key = [0x0b; KEY_LEN];
+        let mac = sign(&key, b"Hi There");
+        assert_eq!(mac[..], hmac_sha1(&key, b"Hi There")[..MAC_LEN]);
+        assert!(verify(&key, b"Hi There", &mac));
+
+        let mut flipped = mac;
+        flipped[MAC_LEN - 1] ^= 1;
+        assert!(!verify(&key, b"Hi There", &flipped));
+        assert!(!verify(&key, b"Hi there", &mac));
+        assert!(!verify(&[0x0c; KEY_LEN], b"Hi There", &mac));
+        assert!(!verify(&key, b"Hi There", &mac[..MAC_LEN - 1]));
+    }
+}
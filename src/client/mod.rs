@@ -1,15 +1,43 @@
-use std::f32::consts::PI;
 use std::net::SocketAddr;
-use tokio::io::AsyncWriteExt;
-use tokio::net::TcpStream;
+use std::time::Instant;
+use macroquad::math::Vec3;
+use tokio::io::{AsyncRead, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
 use tokio::sync::{mpsc, oneshot, watch};
-use crate::client::init::{InitData, InitPhase};
-use crate::client::raw_message::RawMessage;
+use crate::client::calibration_store::CalibrationStore;
+use crate::client::calibration::Problem;
+use crate::client::downlink::{Downlink, Phase};
+use crate::client::handshake::{Capabilities, Claim, HandshakeReply, MIN_PROTOCOL_VERSION, PROTOCOL_VERSION, RejectReason, SERVER_CAPABILITIES};
+use crate::client::init::InitPhase;
+use crate::client::latency::{ClockSync, Latency, LatencyStats, LOG_INTERVAL, PING_INTERVAL};
+use crate::client::liveness::{HEARTBEAT_INTERVAL, Liveness, STALE_AFTER};
+use crate::client::mac::Key;
+use crate::client::orientation::Orientation;
+use crate::client::pairing::Pairing;
+use crate::client::position_manager::PositionManager;
+use crate::client::raw_message::{Policy, RawMessage};
+use crate::client::recording::{Recorded, Recorder};
+use crate::client::session::{ParkedSessions, Player, Session, Slots};
 
+pub mod calibration;
+pub mod calibration_store;
+pub mod datagram;
+pub mod discovery;
+pub mod downlink;
 pub mod fake;
+pub mod handshake;
 pub mod init;
-mod raw_message;
+pub mod latency;
+pub mod liveness;
+pub mod mac;
+pub mod orientation;
+pub mod pairing;
+mod rate_limit;
+pub mod raw_message;
+pub mod recording;
 pub mod position_manager;
+pub mod session;
+pub mod websocket;
 
 type SensorData = (f32, f32, f32);
 pub type PosCoord = (f32, f32);
@@ -17,94 +45,475 @@ type ShooterCoord = (f32, f32, f32);
 
 #[derive(Debug)]
 pub enum Message {
-    Click(PosCoord),
+    /* Where the shot went, and when the trigger was pulled on the server's clock if the controller said */
+    Click(PosCoord, Option<Instant>),
     DoubleClick(PosCoord),
+    /* The player is at this step of an in-game recalibration, with what was wrong with the last side points */
+    Recalibrating(InitPhase, Option<Problem>),
+    /* Back to playing, recalibrated or not */
+    Recalibrated,
     Disconnect,
+    Reconnect,
 }
 
-pub async fn handle(
-    mut tcp_sock: TcpStream, addr: SocketAddr,
-    msg_tx: mpsc::Sender<(u32, Message)>,
-    next_phase_rx: watch::Receiver<Option<InitPhase>>,
-    done_phase_tx: watch::Sender<Option<InitPhase>>,
-    init_data_tx: watch::Sender<Option<InitData>>,
-    window_size: (f32, f32),
-) -> Option<u32> {
+/*
+    Shared by every connection the listener accepts
+ */
+#[derive(Clone)]
+pub struct Context {
+    pub window_size: (f32, f32),
+    pub msg_tx: mpsc::Sender<(u32, Message)>,
+    pub players_tx: mpsc::UnboundedSender<Player>,
+    pub lobby_open_rx: watch::Receiver<bool>,
+    /* Only while a match is being played, not on the tutorial or the results */
+    pub match_running_rx: watch::Receiver<bool>,
+    pub parked: ParkedSessions,
+    pub slots: Slots,
+    pub pairing: Pairing,
+    pub liveness: Liveness,
+    pub latency: Latency,
+    pub recorder: Option<Recorder>,
+    pub pos_man: PositionManager,
+    pub calibrations: CalibrationStore,
+}
+
+/*
+    Anything a controller can talk the TCP framing over, so other transports can bridge into it
+ */
+pub trait Connection: AsyncRead + AsyncWrite + Unpin + Send + 'static {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send + 'static> Connection for T {}
+
+pub async fn listen(listener: TcpListener, context: Context) {
+    loop {
+        let Ok((tcp_sock, addr)) = listener.accept().await else {
+            continue;
+        };
+        match &context.recorder {
+            Some(recorder) => tokio::spawn(handle(Recorded::new(tcp_sock, addr, recorder.clone()), addr, context.clone())),
+            None => tokio::spawn(handle(tcp_sock, addr, context.clone())),
+        };
+    }
+}
+
+/*
+    A handshake either picks up a parked session, or joins as a new player while the lobby is open.
+    Anything claiming by index has to get past pairing first, parked sessions included.
+    A controller that agreed to AUTH gets a fresh datagram key with every accept, resumes too.
+    A new player whose controller has a saved calibration starts out with it.
+ */
+pub async fn handle<S: Connection>(mut sock: S, addr: SocketAddr, context: Context) {
     println!("Handling connection of client {addr}");
 
-    let (index_tx, index_rx) = oneshot::channel();
+    let Some((claim, capabilities, name, pin, identity)) = read_handshake(&mut sock, addr).await else {
+        return;
+    };
 
-    tokio::spawn(async move {
-        let mut phase;
-        let mut init_data = InitData::new(window_size);
-        let mut shooter: ShooterCoord = (0.0, 0.0, 0.0);
-        let Some(RawMessage::SetIndex(index)) = RawMessage::read(&mut tcp_sock).await else {
-            println!("Client {addr} didn't advertise its index as its first message - maybe old client. Dropping.");
-            tcp_sock.shutdown().await.unwrap();
-            index_tx.send(None).unwrap();
+    if let Claim::Index(_) = claim {
+        if let Err(reason) = context.pairing.check(addr.ip(), pin) {
+            reject(&mut sock, capabilities, reason).await;
             return;
-        };
-        index_tx.send(Some(index)).unwrap();
+        }
+    }
+
+    let key = capabilities.filter(|x| x.has(Capabilities::AUTH)).map(|_| mac::random_key());
+
+    if let Some(session) = context.parked.claim(claim) {
+        if !accept(&mut sock, addr, &session, capabilities, key).await {
+            context.parked.park(session);
+            return;
+        }
+        context.pos_man.rebind(addr, session.token, key);
+
+        let index = session.index;
+        println!("Client {addr} resumed id {index}");
+        context.msg_tx.send((index, Message::Reconnect)).await.unwrap();
+
+        tokio::spawn(run(sock, session, capabilities, context.clone()));
+        return;
+    }
+
+    let Claim::Index(index) = claim else {
+        println!("Client {addr} has no session to resume for {:?}. Rejecting.", claim);
+        reject(&mut sock, capabilities, RejectReason::NoSuchSession).await;
+        return;
+    };
+
+    if !*context.lobby_open_rx.borrow() {
+        println!("Client {addr} tried to join while a match is running. Rejecting.");
+        reject(&mut sock, capabilities, RejectReason::MatchInProgress).await;
+        return;
+    }
+
+    /* The accept reply carries the index, so a reassigned controller learns its real slot */
+    let Some(assigned) = context.slots.take(index) else {
+        println!("Client {addr} wanted id {index} but all slots are taken. Rejecting.");
+        reject(&mut sock, capabilities, RejectReason::LobbyFull).await;
+        return;
+    };
+    if assigned != index {
+        println!("Client {addr} wanted id {index}, which is taken or out of range. Reassigned to id {assigned}.");
+    }
+    let index = assigned;
+
+    let (token, init_data_tx, pos_rx) = context.pos_man.register(addr, key);
+    let (mut session, player) = Session::open(index, token, name, identity, context.window_size, init_data_tx, pos_rx);
+    if let Some(saved) = session.identity.as_deref().and_then(|x| context.calibrations.get(x)) {
+        println!("Client {addr} has a saved calibration");
+        session.restore(saved);
+    }
+
+    if !accept(&mut sock, addr, &session, capabilities, key).await {
+        context.pos_man.remove(token);
+        context.slots.release(index);
+        return;
+    }
 
-        println!("Client {addr} connected with id {index}");
+    println!("Client {addr} connected with id {index}");
 
+    tokio::spawn(run(sock, session, capabilities, context.clone()));
+    context.players_tx.send(player).ok();
+}
+
+/*
+    Capabilities are None for legacy SetIndex clients, which never read from the socket.
+    The identity saved calibrations go by is the controller's own id, controllers before v3 have none.
+ */
+async fn read_handshake<S: Connection>(sock: &mut S, addr: SocketAddr) -> Option<(Claim, Option<Capabilities>, String, Option<u32>, Option<String>)> {
+    match RawMessage::read(sock).await {
+        Ok(RawMessage::Hello(hello)) => {
+            if !hello.is_version_supported() {
+                println!("Client {addr} speaks protocol v{}, server supports v{MIN_PROTOCOL_VERSION}-v{PROTOCOL_VERSION}. Rejecting.", hello.version());
+                HandshakeReply::Reject(RejectReason::UnsupportedVersion).write(sock).await.ok();
+                sock.shutdown().await.ok();
+                return None;
+            }
+
+            let capabilities = hello.capabilities().intersect(SERVER_CAPABILITIES);
+            println!(
+                "Client {addr} \"{}\" (model {}, protocol v{}, capabilities {:#06b}) handshaked",
+                hello.name(), hello.model(), hello.version(), capabilities.bits()
+            );
+            let name = hello.name().to_string();
+            let identity = hello.id().map(String::from);
+            Some((hello.claim(), Some(capabilities), name, hello.pin(), identity))
+        }
+        Ok(RawMessage::SetIndex(index)) => {
+            println!("Client {addr} used legacy SetIndex handshake");
+            Some((Claim::Index(index), None, String::new(), None, None))
+        }
+        Ok(_) => {
+            println!("Client {addr} didn't send a handshake as its first message - maybe old client. Dropping.");
+            sock.shutdown().await.ok();
+            None
+        }
+        Err(e) => {
+            println!("Client {addr} failed its handshake with {e}. Dropping.");
+            sock.shutdown().await.ok();
+            None
+        }
+    }
+}
+
+async fn accept<S: Connection>(sock: &mut S, addr: SocketAddr, session: &Session, capabilities: Option<Capabilities>, key: Option<Key>) -> bool {
+    let Some(capabilities) = capabilities else {
+        return true;
+    };
+
+    let reply = HandshakeReply::Accept { index: session.index, capabilities, session: session.token, key };
+    if let Err(e) = reply.write(sock).await {
+        println!("Client {addr} dropped while accepting handshake: {e}");
+        return false;
+    }
+    true
+}
+
+async fn reject<S: Connection>(sock: &mut S, capabilities: Option<Capabilities>, reason: RejectReason) {
+    if capabilities.is_some() {
+        HandshakeReply::Reject(reason).write(sock).await.ok();
+    }
+    sock.shutdown().await.ok();
+}
+
+async fn run<S: Connection>(
+    sock: S,
+    mut session: Session,
+    capabilities: Option<Capabilities>,
+    context: Context,
+) {
+    let Context { msg_tx, parked, pos_man, liveness, latency, calibrations, match_running_rx, .. } = context;
+    let index = session.index;
+    let window_size = session.init_data.window_size();
+    let mut phase;
+    /* A held double-click's own calibration steps, and what to go back to if it's called off */
+    let mut recalibration: Option<(InitPhase, init::InitData, ShooterCoord)> = None;
+    session.init_data.set_roll_compensation(capabilities.is_some_and(|x| x.has(Capabilities::ROLL)));
+
+    let (mut sock_read, mut sock_write) = tokio::io::split(sock);
+
+    /*
+        The writer hands the downlink receiver back when the connection ends,
+        so it can be parked with the rest of the session
+     */
+    let (stop_tx, mut stop_rx) = oneshot::channel::<()>();
+    let mut downlink_rx = session.downlink_rx;
+    let writer = tokio::spawn(async move {
         loop {
-            let raw_message = RawMessage::read(&mut tcp_sock).await;
-            phase = *next_phase_rx.borrow();
-
-            if let None = &phase { /* Initialize is done and game is running */
-                done_phase_tx.send(None).unwrap();
-                init_data_tx.send(Some(init_data)).unwrap();
-            } else { /* Initialize under progress */
-                init_data_tx.send(None).unwrap();
+            let message = tokio::select! {
+                message = downlink_rx.recv() => message,
+                _ = &mut stop_rx => None,
+            };
+            let Some(message) = message else {
+                break;
+            };
+
+            /* Legacy clients never read from the socket, so they get no downlink at all */
+            let Some(capabilities) = capabilities else {
+                continue;
+            };
+            if let Downlink::Vibrate(_) = message {
+                if !capabilities.has(Capabilities::HAPTICS) {
+                    continue;
+                }
+            }
+            if let Err(e) = message.write(&mut sock_write).await {
+                println!("Downlink to client {index} closed: {e}");
+                break;
             }
+        }
+        downlink_rx
+    });
 
-            if let Some(raw_message) = raw_message {
-                if let Some(p) = &phase {
-                    if let RawMessage::Click(data) = raw_message {
-                        match p {
-                            InitPhase::WaitMonitor => {
-                                init_data.set_monitor(data);
-                                println!("Wait monitor {index} done")
-                            }
-                            InitPhase::WaitFirstPoint => {
-                                init_data.set_first_point(data);
-                                println!("Wait first point {index} done")
+    /*
+        Reads happen in their own task, as read_exact loses data if cancelled halfway.
+        Any TCP message or UDP datagram counts as a sign of life.
+        Only decode errors whose policy says so end the connection, the rest are logged and skipped.
+     */
+    let (raw_tx, mut raw_rx) = mpsc::channel(16);
+    let reader = tokio::spawn(async move {
+        loop {
+            let raw_message = match RawMessage::read(&mut sock_read).await {
+                Ok(raw_message) => raw_message,
+                Err(e) => match e.policy() {
+                    Policy::Ignore => {
+                        println!("Ignoring {e} from client {index}");
+                        continue;
+                    }
+                    Policy::Warn => {
+                        println!("Warning: client {index} sent a {e}");
+                        continue;
+                    }
+                    Policy::Disconnect => {
+                        println!("Client {index} disconnected: {e}");
+                        break;
+                    }
+                },
+            };
+            if raw_tx.send(raw_message).await.is_err() {
+                break;
+            }
+        }
+    });
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    let mut last_tcp = Instant::now();
+
+    /* Legacy clients never read, so they'd never answer a ping */
+    let mut ping = tokio::time::interval(PING_INTERVAL);
+    let mut ping_id = 0u32;
+    let mut ping_sent: Option<(u32, Instant)> = None;
+    let mut latency_stats = LatencyStats::default();
+    let mut clock_sync = ClockSync::new();
+    let mut last_latency_log = Instant::now();
+
+    loop {
+        let raw_message = tokio::select! {
+            raw_message = raw_rx.recv() => raw_message,
+            _ = heartbeat.tick() => {
+                let last_seen = pos_man.last_seen(session.token).map_or(last_tcp, |x| x.max(last_tcp));
+                let silence = last_seen.elapsed();
+                liveness.set_stale(index, silence >= STALE_AFTER);
+                if silence >= liveness.grace_period() {
+                    println!("Client {index} was silent for {}s, dropping connection", silence.as_secs());
+                    break;
+                }
+
+                if let Some(stream_stats) = pos_man.stream_stats(session.token) {
+                    latency_stats.set_udp(&stream_stats);
+                }
+                latency.update(index, latency_stats);
+                if last_latency_log.elapsed() >= LOG_INTERVAL {
+                    last_latency_log = Instant::now();
+                    log_latency(index, &latency_stats);
+                }
+                continue;
+            }
+            _ = ping.tick(), if capabilities.is_some() => {
+                ping_id = ping_id.wrapping_add(1);
+                ping_sent = Some((ping_id, Instant::now()));
+                session.downlink_tx.send(Downlink::Ping(ping_id)).ok();
+                continue;
+            }
+        };
+        last_tcp = Instant::now();
+
+        if let Some(RawMessage::Pong(id, timestamp)) = raw_message {
+            /* Only the latest ping counts, an answer to an older one says nothing new */
+            if let Some((sent_id, sent)) = ping_sent {
+                if sent_id == id {
+                    latency_stats.add_rtt(sent.elapsed());
+                    clock_sync.add(sent, sent.elapsed(), timestamp);
+                    ping_sent = None;
+                }
+            }
+            continue;
+        }
+        phase = *session.next_phase_rx.borrow();
+        /* The tutorial starting over takes precedence */
+        if phase.is_some() {
+            recalibration = None;
+        }
+        let in_match = *match_running_rx.borrow();
+        let held = matches!(raw_message, Some(RawMessage::HeldDoubleClick));
+
+        /* Called off by another held double-click, or by the match ending halfway through */
+        if let Some((_, init_data, shooter)) = recalibration.filter(|_| held || !in_match) {
+            println!("Client {index} called off its recalibration");
+            recalibration = None;
+            session.init_data = init_data;
+            session.shooter = shooter;
+            session.init_data_tx.send(Some(session.init_data)).ok();
+            if in_match {
+                session.downlink_tx.send(Downlink::PhaseChanged(Phase::Game)).ok();
+            }
+            msg_tx.send((index, Message::Recalibrated)).await.unwrap();
+        } else if held && phase.is_none() && session.calibrated && in_match {
+            println!("Client {index} recalibrates in game");
+            recalibration = Some((InitPhase::WaitMonitor, session.init_data, session.shooter));
+            /* Recalibrating is always the three point way, an old grid fit would outrank it */
+            session.init_data.set_fit(None);
+            session.init_data_tx.send(None).ok();
+            session.downlink_tx.send(Downlink::PhaseChanged(Phase::Init(InitPhase::WaitMonitor))).ok();
+            msg_tx.send((index, Message::Recalibrating(InitPhase::WaitMonitor, None))).await.unwrap();
+        }
+        if held {
+            continue;
+        }
+        phase = phase.or(recalibration.map(|x| x.0));
+
+        if let None = &phase { /* Initialize is done and game is running, or still in lobby */
+            session.done_phase_tx.send(None).ok();
+            session.init_data_tx.send(if session.calibrated { Some(session.init_data) } else { None }).ok();
+        } else { /* Initialize under progress */
+            session.init_data_tx.send(None).ok();
+        }
+
+        if let Some(raw_message) = raw_message {
+            if let Some(p) = &phase {
+                if let RawMessage::Click(data, _) = raw_message {
+                    match p {
+                        InitPhase::WaitMonitor => {
+                            session.init_data.set_monitor(data);
+                            println!("Wait monitor {index} done")
+                        }
+                        InitPhase::WaitFirstPoint => {
+                            session.init_data.set_first_point(data);
+                            println!("Wait first point {index} done")
+                        }
+                        InitPhase::WaitSecondPoint => {
+                            session.init_data.set_second_point(data);
+                            let problem = calibration::check(&session.init_data).err();
+                            if let Some(problem) = problem {
+                                println!("Side points of {index} turned down: {problem:?}");
                             }
-                            InitPhase::WaitSecondPoint => {
-                                init_data.set_second_point(data);
-                                println!("Wait second point {index} done")
+                            session.problem_tx.send(problem).ok();
+                            println!("Wait second point {index} done")
+                        }
+                        /* Clicking a target again while the others catch up changes nothing, so the fit runs once */
+                        InitPhase::WaitGridPoint { .. } if *session.done_phase_tx.borrow() == Some(*p) => {}
+                        InitPhase::WaitGridPoint { index: point, count } => {
+                            if let Some(target) = calibration::grid_targets(*count, window_size).get(*point as usize) {
+                                session.init_data.set_sample(*point as usize, *target, data);
                             }
-                            InitPhase::Finalize => {
-                                shooter = shooter_pos(&init_data);
-                                init_data_tx.send(Some(init_data)).unwrap();
-                                println!("Wait finalize {index} done")
+                            println!("Wait grid point {} of {count} {index} done", point + 1);
+
+                            if point + 1 == *count {
+                                let fit = calibration::fit(&session.init_data);
+                                session.init_data.set_fit(fit);
+                                session.calibration_tx.send(Some(session.init_data)).ok();
+                                match fit {
+                                    Some(fit) => println!("Grid calibration of {index} fitted, off by {:.1}px on average", fit.residual),
+                                    None => println!("Grid calibration of {index} couldn't be fitted"),
+                                }
                             }
                         }
-                        done_phase_tx.send(Some(*p)).unwrap();
+                        InitPhase::Finalize => {
+                            session.shooter = finalize(index, session.init_data, session.identity.as_deref(), &session.init_data_tx, &session.calibration_tx, &calibrations);
+                            session.calibrated = true;
+                            println!("Wait finalize {index} done")
+                        }
                     }
-                } else {
-                    // if let RawMessage::Position(data) = raw_message {
-                    //     let pos = screen_pos(&init_data, data, shooter);
-                    //     pos_tx.send(pos).unwrap();
-                    //
-                    // } else
-                    if let RawMessage::Click(data) = raw_message {
-                        let pos = screen_pos(&init_data, data, shooter);
-                        msg_tx.send((index, Message::Click(reverse_fix_pos(pos, window_size)))).await.unwrap();
+
+                    /* Recalibration walks the three point steps on its own, main isn't waiting on it */
+                    if let Some((step, ..)) = &mut recalibration {
+                        let problem = (*step == InitPhase::WaitSecondPoint).then(|| *session.problem_tx.borrow()).flatten();
+                        let next = match step {
+                            InitPhase::WaitMonitor => Some(InitPhase::WaitFirstPoint),
+                            InitPhase::WaitSecondPoint if problem.is_some() => Some(InitPhase::WaitFirstPoint),
+                            InitPhase::WaitFirstPoint => Some(InitPhase::WaitSecondPoint),
+                            _ => None,
+                        };
+                        if let Some(next) = next {
+                            *step = next;
+                            session.downlink_tx.send(Downlink::PhaseChanged(Phase::Init(next))).ok();
+                            msg_tx.send((index, Message::Recalibrating(next, problem))).await.unwrap();
+                        } else {
+                            recalibration = None;
+                            session.shooter = finalize(index, session.init_data, session.identity.as_deref(), &session.init_data_tx, &session.calibration_tx, &calibrations);
+                            println!("Client {index} recalibrated in game");
+                            session.downlink_tx.send(Downlink::PhaseChanged(Phase::Game)).ok();
+                            msg_tx.send((index, Message::Recalibrated)).await.unwrap();
+                        }
+                    } else {
+                        session.done_phase_tx.send(Some(*p)).ok();
                     }
                 }
-
-                // if let RawMessage::DoubleClick(_) = raw_message { // Temporary
-                //     phase = Some(init::InitPhase::WaitMonitor);
-                // }
             } else {
-                msg_tx.send((index, Message::Disconnect)).await.unwrap();
+                // if let RawMessage::Position(data) = raw_message {
+                //     let pos = screen_pos(&init_data, data, shooter);
+                //     pos_tx.send(pos).unwrap();
+                //
+                // } else
+                if let RawMessage::Click(data, timestamp) = raw_message {
+                    let pos = screen_pos(&session.init_data, data, session.shooter);
+                    let at = timestamp.and_then(|x| clock_sync.to_server(x));
+                    msg_tx.send((index, Message::Click(reverse_fix_pos(pos, window_size), at))).await.unwrap();
+                } else if let RawMessage::DoubleClick(data) = raw_message {
+                    let pos = screen_pos(&session.init_data, data, session.shooter);
+                    msg_tx.send((index, Message::DoubleClick(reverse_fix_pos(pos, window_size)))).await.unwrap();
+                }
             }
+        } else {
+            break;
         }
-    });
+    }
 
-    index_rx.await.unwrap()
+    /* Parked before announcing the disconnect, so main can discard the session right away */
+    reader.abort();
+    liveness.forget(index);
+    latency.forget(index);
+    log_latency(index, &latency_stats);
+    stop_tx.send(()).ok();
+    session.downlink_rx = writer.await.unwrap();
+    parked.park(session);
+    msg_tx.send((index, Message::Disconnect)).await.unwrap();
+}
+
+fn log_latency(index: u32, stats: &LatencyStats) {
+    let ms = |x: Option<std::time::Duration>| x.map_or(String::from("?"), |x| format!("{}", x.as_millis()));
+    println!(
+        "Latency of client {index}: RTT {}ms (jitter {}ms), UDP {}ms (jitter {}ms)",
+        ms(stats.rtt), stats.rtt_jitter.as_millis(), ms(stats.udp_delay), stats.udp_jitter.as_millis()
+    );
 }
 
 /*
@@ -118,39 +527,49 @@ fn reverse_fix_pos(pos: PosCoord, window_size: (f32, f32)) -> PosCoord {
     (x + width / 2.0, height / 2.0 - y)
 }
 
-fn shooter_pos(init_data: &init::InitData) -> ShooterCoord {
-    let monitor_yaw = init_data.monitor().0;
-    let a_yaw = init_data.first_point().0;
-    let b_yaw = init_data.second_point().0;
-    let avg_pitch = init_data.first_point().1; // Come up with a better value later
-
-
-    let a_tan = ((monitor_yaw - a_yaw + 90.0) * PI / 180.0).tan();
-    let b_tan = ((monitor_yaw - b_yaw + 90.0) * PI / 180.0).tan();
-    let h_tan = (avg_pitch * PI / 180.0).tan();
+/*
+    Settles the shooter of a finished calibration, hands it to the position manager and main, and saves it
+ */
+fn finalize(
+    index: u32,
+    init_data: init::InitData,
+    identity: Option<&str>,
+    init_data_tx: &watch::Sender<Option<init::InitData>>,
+    calibration_tx: &watch::Sender<Option<init::InitData>>,
+    calibrations: &CalibrationStore,
+) -> ShooterCoord {
+    let shooter = shooter_pos(&init_data);
+    if init_data.fit().is_none() {
+        match calibration::sides(&init_data) {
+            Some(sides) if !sides.is_consistent() => println!("Calibration of {index} is inconsistent, the side points disagree on height by {:.1} degrees", sides.mismatch),
+            None => println!("Calibration of {index} is degenerate, the side points don't cross in front of the screen"),
+            _ => {}
+        }
+    }
+    init_data_tx.send(Some(init_data)).ok();
+    calibration_tx.send(Some(init_data)).ok();
+    if let Some(identity) = identity {
+        calibrations.save(identity, init_data, shooter);
+    }
+    shooter
+}
 
-    let x = (a_tan + b_tan) / (a_tan - b_tan) * (-init_data.window_size().1 / 2.0);
-    let y = a_tan * (x + init_data.window_size().1 / 2.0);
-    let h = (x * x + y * y).sqrt() * h_tan;
+/*
+    The first and second points sit at the screen's vertical center, half a screen height
+    left and right of its center. Their aiming lines cross where the shooter stands.
+    Grid calibration has already fitted the shooter, see calibration.rs.
+ */
+fn shooter_pos(init_data: &init::InitData) -> ShooterCoord {
+    if let Some(fit) = init_data.fit() {
+        return fit.shooter;
+    }
 
-    return (x, y, h);
+    /* Straight in front, a screen width away, beats aiming from nowhere */
+    calibration::sides(init_data).map_or((0.0, -init_data.window_size().0, 0.0), |x| x.shooter)
 }
 
-fn screen_pos(init_data: &init::InitData, curr_data: SensorData,  shooter_pos: ShooterCoord) -> PosCoord {
-    let monitor_yaw = init_data.monitor().0;
-    let monitor_pitch = init_data.monitor().1;
-    let curr_yaw = curr_data.0;
-    let curr_pitch = curr_data.1;
-
-    let xy_tan = ((monitor_yaw - curr_yaw + 90.0) * PI / 180.0).tan();
+fn screen_pos(init_data: &init::InitData, curr_data: Orientation, shooter_pos: ShooterCoord) -> PosCoord {
+    let (frame, normal) = calibration::screen_plane(init_data.monitor(), init_data.yaw_offset());
     let (x, y, h) = shooter_pos;
-
-    let my_tan = ((90.0 - monitor_pitch) * PI / 180.0).tan();
-    let my_cos = (monitor_pitch * PI / 180.0).cos();
-    let cy_tan = (curr_pitch * PI / 180.0).tan();
-
-    let retx = x - y / xy_tan;
-    let rety = ((x * x + y * y).sqrt() * -cy_tan + h) / (my_tan + cy_tan) * my_tan / my_cos;
-
-    return (retx, rety);
+    calibration::project(&frame, normal, Vec3::new(x, y, h), init_data.aim(curr_data))
 }
@@ -0,0 +1,100 @@
+use std::f32::consts::PI;
+use macroquad::math::{Quat, Vec3};
+use crate::client::SensorData;
+
+/*
+    World frame is x east, y north, z up. The gun points along the device's +y axis.
+ */
+const DEVICE_FORWARD: Vec3 = Vec3::Y;
+
+#[derive(Copy, Clone, Debug)]
+pub enum Orientation {
+    /* Yaw clockwise from north, pitch positive pointing down, roll, all in degrees */
+    Euler(SensorData),
+    /* Unit quaternion rotating the device frame into the world frame */
+    Quaternion(Quat),
+}
+
+impl Orientation {
+    pub fn from_quaternion(w: f32, x: f32, y: f32, z: f32) -> Orientation {
+        Orientation::Quaternion(Quat::from_xyzw(x, y, z, w).normalize())
+    }
+
+    /*
+        Direction the gun is pointing in, which is all the projection needs.
+        Roll doesn't change it, and there's no gimbal lock to run into.
+     */
+    pub fn forward(&self) -> Vec3 {
+        match self {
+            Orientation::Euler((yaw, pitch, _)) => {
+                let (yaw, pitch) = (yaw * PI / 180.0, pitch * PI / 180.0);
+                Vec3::new(yaw.sin() * pitch.cos(), yaw.cos() * pitch.cos(), -pitch.sin())
+            }
+            Orientation::Quaternion(q) => q.mul_vec3(DEVICE_FORWARD),
+        }
+    }
+
+    /*
+        Controllers with ROLL measure yaw and pitch in the gun's frame, so canted clockwise by roll,
+        moving the barrel right reads as right and up. This turns how far it moved from the reference back by the roll.
+        Quaternions know where the barrel points either way and are left alone.
+     */
+    pub fn unrolled(&self, reference: Orientation) -> Orientation {
+        let (Orientation::Euler((yaw, pitch, roll)), Orientation::Euler((ref_yaw, ref_pitch, _))) = (*self, reference) else {
+            return *self;
+        };
+        let right = (yaw - ref_yaw + 180.0).rem_euclid(360.0) - 180.0;
+        let down = pitch - ref_pitch;
+        let (sin, cos) = (roll * PI / 180.0).sin_cos();
+
+        Orientation::Euler(((ref_yaw + right * cos - down * sin).rem_euclid(360.0), ref_pitch + right * sin + down * cos, roll))
+    }
+
+    /* A zero quaternion normalizes to NaN, so this catches those too */
+    pub fn is_finite(&self) -> bool {
+        match self {
+            Orientation::Euler((yaw, pitch, roll)) => yaw.is_finite() && pitch.is_finite() && roll.is_finite(),
+            Orientation::Quaternion(q) => q.is_finite(),
+        }
+    }
+}
+
+impl Default for Orientation {
+    fn default() -> Self {
+        Orientation::Euler((0.0, 0.0, 0.0))
+    }
+}
+
+/*
+    Screen space as seen from the monitor calibration:
+    x to the right along the screen, y towards the screen, z up
+ */
+#[derive(Copy, Clone, Debug)]
+pub struct ScreenFrame {
+    right: Vec3,
+    towards: Vec3,
+}
+
+impl ScreenFrame {
+    pub fn new(monitor: Orientation) -> ScreenFrame {
+        let forward = monitor.forward();
+        let towards = Vec3::new(forward.x, forward.y, 0.0).normalize_or_zero();
+        let right = Vec3::new(towards.y, -towards.x, 0.0);
+
+        ScreenFrame { right, towards }
+    }
+
+    /* The same frame turned clockwise around the vertical by angle radians, the way yaw turns */
+    pub fn turned(&self, angle: f32) -> ScreenFrame {
+        let (sin, cos) = angle.sin_cos();
+        let towards = Vec3::new(self.towards.x * cos + self.towards.y * sin, self.towards.y * cos - self.towards.x * sin, 0.0);
+        let right = Vec3::new(towards.y, -towards.x, 0.0);
+
+        ScreenFrame { right, towards }
+    }
+
+    pub fn local(&self, orientation: Orientation) -> Vec3 {
+        let forward = orientation.forward();
+        Vec3::new(forward.dot(self.right), forward.dot(self.towards), forward.z)
+    }
+}
@@ -0,0 +1,74 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::client::handshake::RejectReason;
+
+/* Wrong PINs allowed from one address before it's locked out for LOCKOUT */
+pub const MAX_ATTEMPTS: u32 = 3;
+pub const LOCKOUT: Duration = Duration::from_secs(30);
+
+/*
+    Optional pairing mode: the lobby shows a PIN and a fresh join has to send it in the handshake.
+    Resumes don't need it, the session token they carry was only ever given to a paired controller.
+ */
+#[derive(Clone, Default)]
+pub struct Pairing {
+    pin: Option<u32>,
+    failures: Arc<Mutex<HashMap<IpAddr, (u32, Instant)>>>,
+}
+
+impl Pairing {
+    pub fn new(pin: u32) -> Self {
+        Self {
+            pin: Some(pin),
+            failures: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /* Always 4 digits, and never 0, which is what controllers send when they have no PIN */
+    pub fn random() -> Self {
+        Self::new(rand::random::<u32>() % 9000 + 1000)
+    }
+
+    pub fn pin(&self) -> Option<u32> {
+        self.pin
+    }
+
+    pub fn is_required(&self) -> bool {
+        self.pin.is_some()
+    }
+
+    pub(super) fn check(&self, ip: IpAddr, pin: Option<u32>) -> Result<(), RejectReason> {
+        let Some(expected) = self.pin else {
+            return Ok(());
+        };
+
+        let mut failures = self.failures.lock().unwrap();
+        if let Some((count, last)) = failures.get(&ip) {
+            if last.elapsed() >= LOCKOUT {
+                failures.remove(&ip);
+            } else if *count >= MAX_ATTEMPTS {
+                println!("Client {ip} is locked out of pairing for another {}s", (LOCKOUT - last.elapsed()).as_secs());
+                return Err(RejectReason::TooManyAttempts);
+            }
+        }
+
+        if pin == Some(expected) {
+            failures.remove(&ip);
+            return Ok(());
+        }
+
+        let (count, last) = failures.entry(ip).or_insert((0, Instant::now()));
+        *count += 1;
+        *last = Instant::now();
+        match pin {
+            Some(_) => println!("Client {ip} sent a wrong pairing PIN ({count}/{MAX_ATTEMPTS})"),
+            None => println!("Client {ip} sent no pairing PIN ({count}/{MAX_ATTEMPTS})"),
+        }
+        if *count >= MAX_ATTEMPTS {
+            println!("Client {ip} is locked out of pairing for {}s", LOCKOUT.as_secs());
+        }
+        Err(RejectReason::WrongPin)
+    }
+}
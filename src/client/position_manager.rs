@@ -1,61 +1,333 @@
 use std::collections::HashMap;
-use std::net::SocketAddr;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
 use tokio::net::UdpSocket;
 use tokio::sync::watch;
+use crate::client::datagram::{Datagram, MAX_LEN, ParseError};
 use crate::client::init::InitData;
-use crate::client::{PosCoord, screen_pos, SensorData, shooter_pos};
+use crate::client::mac::{self, Key, MAC_LEN};
+use crate::client::orientation::Orientation;
+use crate::client::rate_limit::RateLimiter;
+use crate::client::recording::Recorder;
+use crate::client::{PosCoord, screen_pos, shooter_pos};
 
+/* A backwards jump larger than this is a controller restarting its counter, not a late packet */
+const SEQ_RESTART_WINDOW: i32 = 1000;
+/*
+    Datagrams a second any one IP may send, and how many it may send at once.
+    A controller streams at about 60Hz, and a simulator runs up to MAX_PLAYERS of them from one IP.
+ */
+const SOURCE_RATE: f32 = 500.0;
+const SOURCE_BURST: f32 = 100.0;
+
+/*
+    Datagrams thrown away before they got to any stream
+ */
+#[derive(Copy, Clone, Debug, Default)]
+pub struct DropStats {
+    /* Over the per-source rate cap */
+    pub rate_limited: u64,
+    pub short: u64,
+    pub malformed: u64,
+    /* Matching no session, or from somewhere other than the address that paired it */
+    pub unknown_source: u64,
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+pub struct StreamStats {
+    pub received: u64,
+    pub lost: u64,
+    pub stale: u64,
+    /* Datagrams dropped for a missing or wrong MAC */
+    pub unauthenticated: u64,
+    pub last_seq: Option<u32>,
+    pub last_timestamp: u32,
+    /* Interarrival jitter as in RTP, and smoothed transit time above the lowest seen, both in ms */
+    pub jitter_ms: f32,
+    pub queueing_ms: f32,
+    last_transit: Option<i32>,
+    min_transit: Option<i32>,
+}
+
+impl StreamStats {
+    /*
+        A signed stream can't restart its counter without reconnecting, which resets it anyway.
+        Otherwise anything captured off the air would be good to send again once it's old enough.
+     */
+    fn accept(&mut self, seq: u32, timestamp: u32, arrival: u32, signed: bool) -> bool {
+        self.received += 1;
+
+        if let Some(last_seq) = self.last_seq {
+            let diff = seq.wrapping_sub(last_seq) as i32;
+            if diff <= 0 && (signed || diff > -SEQ_RESTART_WINDOW) {
+                self.stale += 1;
+                return false;
+            }
+            if diff > 0 {
+                self.lost += (diff - 1) as u64;
+            } else {
+                self.restart();
+            }
+        }
+
+        self.last_seq = Some(seq);
+        self.last_timestamp = timestamp;
+        self.track_transit(arrival.wrapping_sub(timestamp) as i32);
+        true
+    }
+
+    /* The controller restarted, and its clock most likely with it */
+    fn restart(&mut self) {
+        self.last_seq = None;
+        self.last_transit = None;
+        self.min_transit = None;
+    }
+
+    /*
+        Transit is arrival on our clock minus send time on the controller's, so it carries an
+        unknown clock offset. Differences between transits don't, which is all jitter and queueing need.
+     */
+    fn track_transit(&mut self, transit: i32) {
+        if let Some(last_transit) = self.last_transit {
+            let d = transit.wrapping_sub(last_transit).unsigned_abs() as f32;
+            self.jitter_ms += (d - self.jitter_ms) / 16.0;
+        }
+        self.last_transit = Some(transit);
+
+        let min_transit = match self.min_transit {
+            Some(x) if transit.wrapping_sub(x) >= 0 => x,
+            _ => transit,
+        };
+        self.min_transit = Some(min_transit);
+        self.queueing_ms += (transit.wrapping_sub(min_transit) as f32 - self.queueing_ms) / 16.0;
+    }
+}
+
+struct Stream {
+    init_data_rx: watch::Receiver<Option<InitData>>,
+    pos_tx: watch::Sender<PosCoord>,
+    stats: StreamStats,
+    last_seen: Instant,
+    peer: IpAddr,
+    key: Option<Key>,
+}
+
+/*
+    Streams are keyed by the session token handed out at handshake.
+    Datagrams without a token fall back to the last stream registered from the sender's IP.
+    Clones share the same streams, so sessions can be registered or rebound while run is going.
+    With pairing on, a stream only takes datagrams from the address that paired it.
+    A stream with a key only takes session datagrams signed with it, see mac.rs.
+ */
+#[derive(Clone)]
 pub struct PositionManager {
-    init_datas: HashMap<String, watch::Receiver<Option<InitData>>>,
-    pos_txs: HashMap<String, watch::Sender<PosCoord>>,
+    streams: Arc<Mutex<HashMap<u32, Stream>>>,
+    ip_tokens: Arc<Mutex<HashMap<String, u32>>>,
+    stats_tx: Arc<watch::Sender<HashMap<u32, StreamStats>>>,
+    drops_tx: Arc<watch::Sender<DropStats>>,
+    recorder: Option<Recorder>,
+    paired_only: bool,
+    started: Instant,
 }
 
 impl PositionManager {
     pub fn new() -> Self {
+        let (stats_tx, _) = watch::channel(HashMap::new());
+        let (drops_tx, _) = watch::channel(DropStats::default());
         Self {
-            init_datas: HashMap::new(),
-            pos_txs: HashMap::new(),
+            streams: Arc::new(Mutex::new(HashMap::new())),
+            ip_tokens: Arc::new(Mutex::new(HashMap::new())),
+            stats_tx: Arc::new(stats_tx),
+            drops_tx: Arc::new(drops_tx),
+            recorder: None,
+            paired_only: false,
+            started: Instant::now(),
         }
     }
 
-    pub fn register(&mut self, addr: SocketAddr) -> (watch::Sender<Option<InitData>>, watch::Receiver<PosCoord>) {
+    /* Set before cloning, clones made earlier won't record */
+    pub fn set_recorder(&mut self, recorder: Recorder) {
+        self.recorder = Some(recorder);
+    }
+
+    /* Same as set_recorder, set before cloning */
+    pub fn require_pairing(&mut self) {
+        self.paired_only = true;
+    }
+
+    pub fn register(&self, addr: SocketAddr, key: Option<Key>) -> (u32, watch::Sender<Option<InitData>>, watch::Receiver<PosCoord>) {
         let (init_data_tx, init_data_rx) = watch::channel(None);
         let (pos_tx, pos_rx) = watch::channel((0., 0.));
 
-        println!("Inserted init data rx for {addr}");
-        self.init_datas.insert(addr.ip().to_string(), init_data_rx);
-        self.pos_txs.insert(addr.ip().to_string(), pos_tx);
+        let mut streams = self.streams.lock().unwrap();
+        let mut token = rand::random::<u32>();
+        while token == 0 || streams.contains_key(&token) {
+            token = rand::random::<u32>();
+        }
+
+        println!("Inserted init data rx for {addr} with session {token:08x}");
+        streams.insert(token, Stream {
+            init_data_rx,
+            pos_tx,
+            stats: StreamStats::default(),
+            last_seen: Instant::now(),
+            peer: addr.ip(),
+            key,
+        });
+        self.ip_tokens.lock().unwrap().insert(addr.ip().to_string(), token);
 
-        (init_data_tx, pos_rx)
+        (token, init_data_tx, pos_rx)
     }
 
-    pub async fn run(&mut self, server_addr: &str) {
+    /* The new connection may have a new key, and counts from scratch */
+    pub fn rebind(&self, addr: SocketAddr, token: u32, key: Option<Key>) {
+        self.ip_tokens.lock().unwrap().insert(addr.ip().to_string(), token);
+        if let Some(stream) = self.streams.lock().unwrap().get_mut(&token) {
+            stream.last_seen = Instant::now();
+            stream.peer = addr.ip();
+            stream.key = key;
+            stream.stats.restart();
+        }
+    }
+
+    /* Once a session is gone for good, its datagrams count as from an unknown source */
+    pub fn remove(&self, token: u32) {
+        if self.streams.lock().unwrap().remove(&token).is_some() {
+            println!("Removed stream of session {token:08x}");
+        }
+        self.ip_tokens.lock().unwrap().retain(|_, x| *x != token);
+        self.stats_tx.send_modify(|x| { x.remove(&token); });
+    }
+
+    pub fn last_seen(&self, token: u32) -> Option<Instant> {
+        self.streams.lock().unwrap().get(&token).map(|x| x.last_seen)
+    }
+
+    pub fn stream_stats(&self, token: u32) -> Option<StreamStats> {
+        self.streams.lock().unwrap().get(&token).map(|x| x.stats)
+    }
+
+    pub fn stats(&self) -> watch::Receiver<HashMap<u32, StreamStats>> {
+        self.stats_tx.subscribe()
+    }
+
+    pub fn drops(&self) -> watch::Receiver<DropStats> {
+        self.drops_tx.subscribe()
+    }
+
+    /*
+        Anything longer than MAX_LEN comes in cut short, which no datagram type fits,
+        so the length check in parse catches it
+     */
+    pub async fn run(&self, server_addr: &str) {
         let sock = UdpSocket::bind(server_addr).await.unwrap();
         println!("running udpsock at {}", sock.local_addr().unwrap());
+        let mut rate_limiter = RateLimiter::new(SOURCE_RATE, SOURCE_BURST, "datagrams");
         loop {
-            let mut buf = [0 as u8; 12];
-            if let Ok((_, client_addr)) = sock.recv_from(&mut buf).await {
-                let Some(init_data) = self.init_datas.get(&client_addr.ip().to_string()).map(|x| *x.borrow()).flatten() else {
-                    self.pos_txs.get(&client_addr.ip().to_string()).map(|x| x.send((-500., -500.)));
+            let mut buf = [0u8; MAX_LEN];
+            if let Ok((n, client_addr)) = sock.recv_from(&mut buf).await {
+                if !rate_limiter.allow(client_addr.ip()) {
+                    self.drops_tx.send_modify(|x| x.rate_limited += 1);
                     continue;
-                };
-
-                let y = [buf[0], buf[1], buf[2], buf[3]];
-                let y = f32::from_be_bytes(y);
+                }
+                self.feed(&buf[..n], client_addr);
+            }
+        }
+    }
 
-                let p = [buf[4], buf[5], buf[6], buf[7]];
-                let p = f32::from_be_bytes(p);
+    /*
+        Handles one datagram, whether it came over UDP or was relayed by another transport
+     */
+    pub fn feed(&self, buf: &[u8], client_addr: SocketAddr) {
+        if let Some(recorder) = &self.recorder {
+            recorder.udp(client_addr, buf);
+        }
 
-                let r = [buf[8], buf[9], buf[10], buf[11]];
-                let r = f32::from_be_bytes(r);
+        let datagram = match Datagram::parse(buf) {
+            Ok(datagram) => datagram,
+            Err(ParseError::Short) => {
+                self.drops_tx.send_modify(|x| x.short += 1);
+                return;
+            }
+            Err(ParseError::Malformed) => {
+                self.drops_tx.send_modify(|x| x.malformed += 1);
+                return;
+            }
+        };
 
-                let sensor_data: SensorData = (y, p, r);
+        let Some(token) = datagram.token().or_else(|| self.ip_tokens.lock().unwrap().get(&client_addr.ip().to_string()).copied()) else {
+            self.drops_tx.send_modify(|x| x.unknown_source += 1);
+            return;
+        };
+        let mut streams = self.streams.lock().unwrap();
+        let Some(stream) = streams.get_mut(&token).filter(|x| !self.paired_only || x.peer == client_addr.ip()) else {
+            self.drops_tx.send_modify(|x| x.unknown_source += 1);
+            return;
+        };
+        if let Some(key) = &stream.key {
+            let signed = match &datagram {
+                Datagram::Orientation { token: Some(_), mac: Some(x), .. } => mac::verify(key, &buf[..buf.len() - MAC_LEN], x),
+                _ => false,
+            };
+            if !signed {
+                stream.stats.unauthenticated += 1;
+                let stats = stream.stats;
+                if stats.unauthenticated == 1 || stats.unauthenticated % 100 == 0 {
+                    println!("Dropped {} unauthenticated datagrams for session {token:08x}, the latest from {client_addr}", stats.unauthenticated);
+                }
+                self.stats_tx.send_modify(|x| { x.insert(token, stats); });
+                return;
+            }
+        }
+        stream.last_seen = Instant::now();
 
-                let shooter_pos = shooter_pos(&init_data);
-                let screen_pos = screen_pos(&init_data, sensor_data, shooter_pos);
+        let Some(init_data) = *stream.init_data_rx.borrow() else {
+            stream.pos_tx.send((-500., -500.)).ok();
+            return;
+        };
 
-                self.pos_txs.get(&client_addr.ip().to_string()).map(|x| x.send(screen_pos).unwrap());
+        let orientation = match datagram {
+            Datagram::Legacy(data) => {
+                stream.stats.received += 1;
+                Orientation::Euler(data)
             }
-        }
+            Datagram::Orientation { seq, timestamp, data, .. } => {
+                if !stream.stats.accept(seq, timestamp, self.started.elapsed().as_millis() as u32, stream.key.is_some()) {
+                    let stats = stream.stats;
+                    self.stats_tx.send_modify(|x| { x.insert(token, stats); });
+                    return;
+                }
+                data
+            }
+        };
+        let stats = stream.stats;
+        self.stats_tx.send_modify(|x| { x.insert(token, stats); });
+
+        let shooter_pos = shooter_pos(&init_data);
+        let screen_pos = screen_pos(&init_data, orientation, shooter_pos);
+
+        stream.pos_tx.send(screen_pos).ok();
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn removed_streams_take_no_more_datagrams() {
+        let pos_man = PositionManager::new();
+        let addr: SocketAddr = "10.0.0.2:5000".parse().unwrap();
+        let (token, _init_data_tx, _pos_rx) = pos_man.register(addr, None);
+
+        pos_man.feed(&[0u8; 12], addr);
+        assert_eq!(pos_man.drops().borrow().unknown_source, 0);
+        assert!(pos_man.last_seen(token).is_some());
+
+        pos_man.remove(token);
+        pos_man.feed(&[0u8; 12], addr);
+        assert_eq!(pos_man.drops().borrow().unknown_source, 1);
+        assert!(pos_man.last_seen(token).is_none());
+    }
+}
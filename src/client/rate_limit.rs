@@ -0,0 +1,84 @@
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::time::{Duration, Instant};
+
+/* Sources quiet for this long are forgotten, so spoofed addresses can't pile up */
+const SOURCE_IDLE: Duration = Duration::from_secs(10);
+
+struct Bucket {
+    tokens: f32,
+    last_refill: Instant,
+    limited: bool,
+}
+
+/*
+    Token bucket per source IP, kept by the receive loop alone.
+    Counting by IP rather than address stops a flood from dodging it by changing ports.
+ */
+pub(super) struct RateLimiter {
+    /* How many a second any one IP may send, and how many at once */
+    rate: f32,
+    burst: f32,
+    /* What's being counted, for the log */
+    what: &'static str,
+    buckets: HashMap<IpAddr, Bucket>,
+    last_prune: Instant,
+}
+
+impl RateLimiter {
+    pub(super) fn new(rate: f32, burst: f32, what: &'static str) -> Self {
+        Self {
+            rate,
+            burst,
+            what,
+            buckets: HashMap::new(),
+            last_prune: Instant::now(),
+        }
+    }
+
+    /* Whether ip has been heard from lately, which it stops being once it goes quiet for a while */
+    pub(super) fn is_known(&self, ip: IpAddr) -> bool {
+        self.buckets.get(&ip).is_some_and(|x| x.last_refill.elapsed() < SOURCE_IDLE)
+    }
+
+    pub(super) fn allow(&mut self, ip: IpAddr) -> bool {
+        let now = Instant::now();
+        if now - self.last_prune >= SOURCE_IDLE {
+            self.buckets.retain(|_, x| now - x.last_refill < SOURCE_IDLE);
+            self.last_prune = now;
+        }
+
+        let bucket = self.buckets.entry(ip).or_insert(Bucket { tokens: self.burst, last_refill: now, limited: false });
+        bucket.tokens = (bucket.tokens + (now - bucket.last_refill).as_secs_f32() * self.rate).min(self.burst);
+        bucket.last_refill = now;
+
+        let limited = bucket.tokens < 1.0;
+        if !limited {
+            bucket.tokens -= 1.0;
+        }
+        if limited != bucket.limited {
+            bucket.limited = limited;
+            if limited {
+                println!("{ip} is sending over {} {} a second, dropping the excess", self.rate, self.what);
+            } else {
+                println!("{ip} is back under the {} rate cap", self.what);
+            }
+        }
+        !limited
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bursts_then_caps_each_source() {
+        let mut limiter = RateLimiter::new(0.001, 5.0, "tests");
+        let (a, b) = (IpAddr::from([10, 0, 0, 1]), IpAddr::from([10, 0, 0, 2]));
+        assert!(!limiter.is_known(a));
+        assert_eq!((0..20).filter(|_| limiter.allow(a)).count(), 5);
+        assert!(limiter.is_known(a));
+        assert!(limiter.allow(b));
+    }
+}
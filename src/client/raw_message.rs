@@ -1,55 +1,162 @@
-use tokio::io::AsyncReadExt;
-use tokio::net::TcpStream;
+use std::fmt;
+use std::io::ErrorKind;
+use tokio::io::{AsyncRead, AsyncReadExt};
+use crate::client::handshake::Handshake;
+use crate::client::orientation::Orientation;
 use crate::client::SensorData;
 
+/* Message types of the 16 byte TCP frames controllers send, in [0..4] */
+pub const CLICK: i32 = 1;
+pub const DOUBLE_CLICK: i32 = 2;
+pub const SET_INDEX: i32 = 3;
+pub const HELLO: i32 = 4;
+pub const RESUME: i32 = 5;
+pub const QUATERNION_CLICK: i32 = 6;
+pub const QUATERNION_DOUBLE_CLICK: i32 = 7;
+pub const PONG: i32 = 9;
+pub const TIMED_CLICK: i32 = 10;
+pub const TIMED_DOUBLE_CLICK: i32 = 11;
+pub const TIMED_QUATERNION_CLICK: i32 = 12;
+pub const TIMED_QUATERNION_DOUBLE_CLICK: i32 = 13;
+pub const HELD_DOUBLE_CLICK: i32 = 14;
+
+/*
+    Why a frame couldn't be turned into a RawMessage. Every frame has its length fixed by its type,
+    so after an unknown type or a bad payload the stream is still in step and reading can go on.
+ */
+#[derive(Debug)]
+pub enum DecodeError {
+    Eof,
+    Io(std::io::Error),
+    /* Includes the deprecated Position (type 0), which is no longer read */
+    UnknownType(i32),
+    Malformed(i32, &'static str),
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Policy {
+    Ignore,
+    Warn,
+    Disconnect,
+}
+
+impl DecodeError {
+    /*
+        Only a dead connection is worth a disconnect. Unknown types are likely a newer controller,
+        a bad payload is a buggy one, so it's worth a louder log line.
+     */
+    pub fn policy(&self) -> Policy {
+        match self {
+            DecodeError::Eof | DecodeError::Io(_) => Policy::Disconnect,
+            DecodeError::UnknownType(_) => Policy::Ignore,
+            DecodeError::Malformed(..) => Policy::Warn,
+        }
+    }
+}
+
+impl From<std::io::Error> for DecodeError {
+    fn from(e: std::io::Error) -> Self {
+        match e.kind() {
+            ErrorKind::UnexpectedEof => DecodeError::Eof,
+            _ => DecodeError::Io(e),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Eof => write!(f, "connection closed"),
+            DecodeError::Io(e) => write!(f, "I/O error: {e}"),
+            DecodeError::UnknownType(0) => write!(f, "deprecated Position message"),
+            DecodeError::UnknownType(x) => write!(f, "unknown message type {x}"),
+            DecodeError::Malformed(x, reason) => write!(f, "malformed message of type {x}: {reason}"),
+        }
+    }
+}
+
 pub enum RawMessage {
     #[deprecated]
     #[allow(dead_code)]
     Position(SensorData),
-    Click(SensorData),
-    DoubleClick(SensorData),
+    /* With the controller's timestamp of the trigger pull, if it sent one */
+    Click(Orientation, Option<u32>),
+    DoubleClick(Orientation),
+    /* The second pull of a double-click kept down, which asks for an in-game recalibration */
+    HeldDoubleClick,
     SetIndex(u32),
+    Hello(Handshake),
+    Pong(u32, u32),
 }
 
 impl RawMessage {
-    pub async fn read(socket: &mut TcpStream) -> Option<RawMessage> {
+    pub async fn read<R: AsyncRead + Unpin>(socket: &mut R) -> Result<RawMessage, DecodeError> {
         let mut buf = vec![0 as u8; 16];
 
-        let n = socket.read_exact(&mut buf).await;
-
-        if let Err(_) = n {
-            return None;
-        }
+        socket.read_exact(&mut buf).await?;
 
         let message_type = [buf[0], buf[1], buf[2], buf[3]];
         let message_type = i32::from_be_bytes(message_type);
 
-        if message_type == 3 {
+        if message_type == SET_INDEX {
             let idx = [buf[4], buf[5], buf[6], buf[7]];
             let idx = u32::from_be_bytes(idx);
 
-            return Some(RawMessage::SetIndex(idx));
+            return Ok(RawMessage::SetIndex(idx));
         }
 
-        let y = [buf[4], buf[5], buf[6], buf[7]];
-        let y = f32::from_be_bytes(y);
+        /* Answer to a downlink Ping, [4..8] the ping's id, [8..12] the controller's clock in ms */
+        if message_type == PONG {
+            let id = u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]);
+            let timestamp = u32::from_be_bytes([buf[8], buf[9], buf[10], buf[11]]);
+            return Ok(RawMessage::Pong(id, timestamp));
+        }
+
+        /* Nothing but the type, the controller tells a held pull from a quick one itself */
+        if message_type == HELD_DOUBLE_CLICK {
+            return Ok(RawMessage::HeldDoubleClick);
+        }
 
-        let p = [buf[8], buf[9], buf[10], buf[11]];
-        let p = f32::from_be_bytes(p);
+        if message_type == HELLO || message_type == RESUME {
+            return Handshake::read_rest(&buf, socket, message_type == RESUME).await.map(RawMessage::Hello);
+        }
+
+        /*
+            Clicks are 1/2 with yaw, pitch, roll, and 6/7 with w, x, y, z running 4 bytes past the usual 16.
+            10/11 and 12/13 are the same with the moment the trigger was pulled in [4..8] first,
+            in ms on the clock the controller stamps its datagrams and pongs with.
+         */
+        let (double, timed, quaternion) = match message_type {
+            CLICK | DOUBLE_CLICK => (message_type == DOUBLE_CLICK, false, false),
+            QUATERNION_CLICK | QUATERNION_DOUBLE_CLICK => (message_type == QUATERNION_DOUBLE_CLICK, false, true),
+            TIMED_CLICK | TIMED_DOUBLE_CLICK => (message_type == TIMED_DOUBLE_CLICK, true, false),
+            TIMED_QUATERNION_CLICK | TIMED_QUATERNION_DOUBLE_CLICK => (message_type == TIMED_QUATERNION_DOUBLE_CLICK, true, true),
+            _ => return Err(DecodeError::UnknownType(message_type)),
+        };
 
-        let r = [buf[12], buf[13], buf[14], buf[15]];
-        let r = f32::from_be_bytes(r);
+        let start = if timed { 8 } else { 4 };
+        let len = start + if quaternion { 16 } else { 12 };
+        if len > buf.len() {
+            buf.resize(len, 0);
+            socket.read_exact(&mut buf[16..]).await?;
+        }
 
-        //println!("{message_type} {y} {p} {r}");
+        let word = |i: usize| [buf[i], buf[i + 1], buf[i + 2], buf[i + 3]];
+        let timestamp = timed.then(|| u32::from_be_bytes(word(4)));
+        let values: Vec<f32> = (start..len).step_by(4).map(|i| f32::from_be_bytes(word(i))).collect();
 
-        if message_type == 0 {
-            // return Some(RawMessage::Position((y, p, r)));
-        } else if message_type == 1 {
-            return Some(RawMessage::Click((y, p, r)));
-        } else if message_type == 2 {
-            return Some(RawMessage::DoubleClick((y, p, r)));
+        if !values.iter().all(|x| x.is_finite()) {
+            return Err(DecodeError::Malformed(message_type, "orientation isn't finite"));
         }
+        let orientation = if quaternion {
+            if values.iter().map(|x| x * x).sum::<f32>() < 1e-6 {
+                return Err(DecodeError::Malformed(message_type, "quaternion has no length"));
+            }
+            Orientation::from_quaternion(values[0], values[1], values[2], values[3])
+        } else {
+            Orientation::Euler((values[0], values[1], values[2]))
+        };
 
-        None
+        Ok(if double { RawMessage::DoubleClick(orientation) } else { RawMessage::Click(orientation, timestamp) })
     }
 }
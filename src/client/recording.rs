@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::task::{Context as TaskContext, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, ReadBuf};
+use crate::client::Context;
+use crate::client::datagram::Datagram;
+use crate::client::handshake::Capabilities;
+use crate::client::mac::{self, Key, KEY_LEN, MAC_LEN};
+
+const MAGIC: &[u8; 8] = b"GYROREC1";
+
+const TCP_OPEN: u8 = 0;
+const TCP_DATA: u8 = 1;
+const TCP_CLOSE: u8 = 2;
+const UDP: u8 = 3;
+const TCP_SENT: u8 = 4;
+
+const ACCEPT_TIMEOUT: Duration = Duration::from_secs(1);
+
+/*
+    Session file layout: the magic, then entries of
+    [0] kind, [1..9] microseconds since recording started, [9] address length,
+    the address as text, a u16 payload length and the payload.
+    TCP data is recorded as the server read it, and what it sent back is kept too,
+    so replay can tell which session token each connection was given.
+ */
+#[derive(Clone)]
+pub struct Recorder {
+    file: Arc<Mutex<File>>,
+    started: Instant,
+}
+
+impl Recorder {
+    pub fn create(path: &str) -> std::io::Result<Recorder> {
+        let mut file = File::create(path)?;
+        file.write_all(MAGIC)?;
+        println!("Recording controller traffic to {path}");
+
+        Ok(Recorder {
+            file: Arc::new(Mutex::new(file)),
+            started: Instant::now(),
+        })
+    }
+
+    pub fn tcp_open(&self, addr: SocketAddr) {
+        self.write(TCP_OPEN, addr, &[]);
+    }
+
+    pub fn tcp_data(&self, addr: SocketAddr, data: &[u8]) {
+        for chunk in data.chunks(u16::MAX as usize) {
+            self.write(TCP_DATA, addr, chunk);
+        }
+    }
+
+    pub fn tcp_sent(&self, addr: SocketAddr, data: &[u8]) {
+        for chunk in data.chunks(u16::MAX as usize) {
+            self.write(TCP_SENT, addr, chunk);
+        }
+    }
+
+    pub fn tcp_close(&self, addr: SocketAddr) {
+        self.write(TCP_CLOSE, addr, &[]);
+    }
+
+    pub fn udp(&self, addr: SocketAddr, data: &[u8]) {
+        self.write(UDP, addr, data);
+    }
+
+    fn write(&self, kind: u8, addr: SocketAddr, payload: &[u8]) {
+        let addr = addr.to_string();
+
+        let mut buf = vec![kind];
+        buf.extend((self.started.elapsed().as_micros() as u64).to_be_bytes());
+        buf.push(addr.len() as u8);
+        buf.extend(addr.as_bytes());
+        buf.extend((payload.len() as u16).to_be_bytes());
+        buf.extend(payload);
+
+        if let Err(e) = self.file.lock().unwrap().write_all(&buf) {
+            println!("Failed to record controller traffic: {e}");
+        }
+    }
+}
+
+/*
+    Passes a connection through untouched, copying everything read and written into the recording
+ */
+pub struct Recorded<S> {
+    inner: S,
+    addr: SocketAddr,
+    recorder: Recorder,
+}
+
+impl<S> Recorded<S> {
+    pub fn new(inner: S, addr: SocketAddr, recorder: Recorder) -> Self {
+        recorder.tcp_open(addr);
+        Self { inner, addr, recorder }
+    }
+}
+
+impl<S> Drop for Recorded<S> {
+    fn drop(&mut self) {
+        self.recorder.tcp_close(self.addr);
+    }
+}
+
+impl<S: AsyncRead + Unpin> AsyncRead for Recorded<S> {
+    fn poll_read(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>> {
+        let before = buf.filled().len();
+        let poll = Pin::new(&mut self.inner).poll_read(cx, buf);
+        if let Poll::Ready(Ok(())) = poll {
+            if buf.filled().len() > before {
+                self.recorder.tcp_data(self.addr, &buf.filled()[before..]);
+            }
+        }
+        poll
+    }
+}
+
+impl<S: AsyncWrite + Unpin> AsyncWrite for Recorded<S> {
+    fn poll_write(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>> {
+        let poll = Pin::new(&mut self.inner).poll_write(cx, buf);
+        if let Poll::Ready(Ok(n)) = poll {
+            self.recorder.tcp_sent(self.addr, &buf[..n]);
+        }
+        poll
+    }
+
+    fn poll_flush(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(mut self: Pin<&mut Self>, cx: &mut TaskContext<'_>) -> Poll<std::io::Result<()>> {
+        Pin::new(&mut self.inner).poll_shutdown(cx)
+    }
+}
+
+struct Entry {
+    kind: u8,
+    time: Duration,
+    addr: SocketAddr,
+    payload: Vec<u8>,
+}
+
+fn parse(data: &[u8]) -> Option<Vec<Entry>> {
+    let mut rest = data.strip_prefix(MAGIC)?;
+    let mut ret = vec![];
+
+    while !rest.is_empty() {
+        let kind = *rest.first()?;
+        let time = u64::from_be_bytes(rest.get(1..9)?.try_into().ok()?);
+        let addr_len = *rest.get(9)? as usize;
+        let addr = std::str::from_utf8(rest.get(10..10 + addr_len)?).ok()?.parse().ok()?;
+        rest = &rest[10 + addr_len..];
+
+        let len = u16::from_be_bytes(rest.get(0..2)?.try_into().ok()?) as usize;
+        let payload = rest.get(2..2 + len)?.to_vec();
+        rest = &rest[2 + len..];
+
+        ret.push(Entry { kind, time: Duration::from_micros(time), addr, payload });
+    }
+
+    Some(ret)
+}
+
+/*
+    Session tokens and datagram keys are random, so the ones in the recording mean nothing to the
+    replaying server. They are matched up through the accept replies, the recorded one and the one
+    replay actually got.
+ */
+/* Either side's accepts, each with the datagram key handed out alongside */
+type Accepts<K, V> = Arc<Mutex<HashMap<K, (V, Option<Key>)>>>;
+
+#[derive(Clone, Default)]
+struct Tokens {
+    /* The recorded token's connection address */
+    recorded: Accepts<u32, SocketAddr>,
+    /* The token replay got for a connection address */
+    replayed: Accepts<SocketAddr, u32>,
+}
+
+impl Tokens {
+    fn accepted(buf: &[u8]) -> Option<u32> {
+        if buf.len() < 16 || i32::from_be_bytes(buf[0..4].try_into().ok()?) != 100 {
+            return None;
+        }
+        Some(u32::from_be_bytes(buf[12..16].try_into().ok()?))
+    }
+
+    /* An accept is followed by a key when AUTH was agreed */
+    fn keyed(buf: &[u8]) -> bool {
+        Tokens::accepted(buf).is_some() && Capabilities::from_bits(u16::from_be_bytes([buf[6], buf[7]])).has(Capabilities::AUTH)
+    }
+
+    fn key(buf: &[u8]) -> Option<Key> {
+        if !Tokens::keyed(buf) {
+            return None;
+        }
+        buf.get(16..16 + KEY_LEN)?.try_into().ok()
+    }
+
+    /* The recorded key, and the replayed token and key */
+    fn translate(&self, token: u32) -> Option<(Option<Key>, u32, Option<Key>)> {
+        let (addr, recorded_key) = *self.recorded.lock().unwrap().get(&token)?;
+        let (token, key) = *self.replayed.lock().unwrap().get(&addr)?;
+        Some((recorded_key, token, key))
+    }
+
+    /*
+        Resume handshakes and session datagrams carry the token in [4..8] or [8..12],
+        depending on the message type. Datagrams that were signed properly get signed again with
+        the replayed key, the ones that weren't are left to fail again.
+     */
+    fn rewrite(&self, buf: &mut [u8], tcp: bool) {
+        if buf.len() < 12 {
+            return;
+        }
+        let message_type = i32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]);
+        let at = match (tcp, message_type) {
+            (true, 5) => 8,
+            (false, 2) | (false, 4) => 4,
+            _ => return,
+        };
+
+        let token = u32::from_be_bytes([buf[at], buf[at + 1], buf[at + 2], buf[at + 3]]);
+        let Some((recorded_key, token, key)) = self.translate(token) else {
+            return;
+        };
+        let signed = match (recorded_key, Datagram::parse(buf)) {
+            (Some(recorded_key), Ok(Datagram::Orientation { mac: Some(x), .. })) => mac::verify(&recorded_key, &buf[..buf.len() - MAC_LEN], &x),
+            _ => false,
+        };
+        buf[at..at + 4].copy_from_slice(&token.to_be_bytes());
+
+        if let (true, Some(key)) = (signed, key) {
+            let len = buf.len() - MAC_LEN;
+            let mac = mac::sign(&key, &buf[..len]);
+            buf[len..].copy_from_slice(&mac);
+        }
+    }
+}
+
+/*
+    Plays a session file back at its original pace. Every recorded TCP connection becomes an
+    in-memory stream into the usual handshake and session handling, and datagrams go straight
+    into the position manager, so decoding and calibration run exactly as they did live.
+ */
+pub async fn replay(path: String, context: Context) {
+    let entries = match std::fs::read(&path) {
+        Ok(data) => parse(&data),
+        Err(e) => {
+            println!("Failed to read recording {path}: {e}");
+            return;
+        }
+    };
+    let Some(entries) = entries else {
+        println!("{path} is not a complete recording, replaying nothing");
+        return;
+    };
+    println!("Replaying {} entries from {path}", entries.len());
+
+    let started = tokio::time::Instant::now();
+    let mut connections = HashMap::new();
+    let tokens = Tokens::default();
+
+    for mut entry in entries {
+        tokio::time::sleep_until(started + entry.time).await;
+
+        match entry.kind {
+            TCP_OPEN => {
+                let (inner, outer) = tokio::io::duplex(1024);
+                let (mut from_server, to_server) = tokio::io::split(outer);
+                /* Nobody is there to read the replies, beyond picking up the session token */
+                let (addr, tokens) = (entry.addr, tokens.clone());
+                tokio::spawn(async move {
+                    let mut buf = [0u8; 16];
+                    while from_server.read_exact(&mut buf).await.is_ok() {
+                        let Some(token) = Tokens::accepted(&buf) else {
+                            continue;
+                        };
+                        let mut key = None;
+                        if Tokens::keyed(&buf) {
+                            let mut buf = [0u8; KEY_LEN];
+                            if from_server.read_exact(&mut buf).await.is_err() {
+                                break;
+                            }
+                            key = Some(buf);
+                        }
+                        tokens.replayed.lock().unwrap().insert(addr, (token, key));
+                    }
+                });
+                tokio::spawn(super::handle(inner, entry.addr, context.clone()));
+                connections.insert(entry.addr, to_server);
+            }
+            TCP_DATA => {
+                tokens.rewrite(&mut entry.payload, true);
+                if let Some(to_server) = connections.get_mut(&entry.addr) {
+                    to_server.write_all(&entry.payload).await.ok();
+                }
+            }
+            TCP_CLOSE => {
+                if let Some(mut to_server) = connections.remove(&entry.addr) {
+                    to_server.shutdown().await.ok();
+                }
+            }
+            TCP_SENT => {
+                let Some(token) = Tokens::accepted(&entry.payload) else {
+                    continue;
+                };
+                tokens.recorded.lock().unwrap().insert(token, (entry.addr, Tokens::key(&entry.payload)));
+
+                /* Datagrams follow right behind an accept, so wait for the replayed one to land */
+                let deadline = tokio::time::Instant::now() + ACCEPT_TIMEOUT;
+                while !tokens.replayed.lock().unwrap().contains_key(&entry.addr) && tokio::time::Instant::now() < deadline {
+                    tokio::time::sleep(Duration::from_millis(1)).await;
+                }
+            }
+            UDP => {
+                tokens.rewrite(&mut entry.payload, false);
+                context.pos_man.feed(&entry.payload, entry.addr);
+            }
+            _ => {}
+        }
+    }
+
+    /*
+        Controllers were still connected when the recording stopped, so their connections stay open.
+        Dropping the ends held here would read as a disconnect, so they're kept until the server exits.
+     */
+    println!("Replay of {path} finished");
+    let _connections = connections;
+    std::future::pending::<()>().await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn addr(port: u16) -> SocketAddr {
+        SocketAddr::from(([192, 168, 0, 2], port))
+    }
+
+    #[test]
+    fn recording_parses_back() {
+        let path = std::env::temp_dir().join(format!("gyrogun_recording_{}.rec", std::process::id()));
+        let recorder = Recorder::create(path.to_str().unwrap()).unwrap();
+        recorder.tcp_open(addr(5000));
+        recorder.tcp_data(addr(5000), &[1, 2, 3]);
+        recorder.udp(addr(5001), &[4, 5]);
+        recorder.tcp_close(addr(5000));
+        drop(recorder);
+
+        let data = std::fs::read(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        let entries = parse(&data).unwrap();
+        let kinds: Vec<u8> = entries.iter().map(|x| x.kind).collect();
+        assert_eq!(kinds, [TCP_OPEN, TCP_DATA, UDP, TCP_CLOSE]);
+        assert_eq!(entries[1].addr, addr(5000));
+        assert_eq!(entries[1].payload, [1, 2, 3]);
+        assert_eq!(entries[2].addr, addr(5001));
+        assert_eq!(entries[2].payload, [4, 5]);
+        assert!(entries.windows(2).all(|x| x[0].time <= x[1].time));
+
+        assert!(parse(&data[..data.len() - 1]).is_none());
+        assert!(parse(b"NOTAREC!").is_none());
+    }
+
+    #[test]
+    fn session_datagrams_get_the_replayed_token_and_key() {
+        let (recorded_key, replayed_key) = ([1u8; KEY_LEN], [2u8; KEY_LEN]);
+        let tokens = Tokens::default();
+        tokens.recorded.lock().unwrap().insert(7, (addr(5000), Some(recorded_key)));
+        tokens.replayed.lock().unwrap().insert(addr(5000), (9, Some(replayed_key)));
+
+        let mut buf = vec![];
+        buf.extend(2i32.to_be_bytes());
+        buf.extend(7u32.to_be_bytes());
+        buf.extend([0u8; 20]);
+        let mac = mac::sign(&recorded_key, &buf);
+        buf.extend(mac);
+
+        tokens.rewrite(&mut buf, false);
+        let len = buf.len() - MAC_LEN;
+        assert_eq!(u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]), 9);
+        assert!(mac::verify(&replayed_key, &buf[..len], &buf[len..]));
+    }
+
+    #[test]
+    fn badly_signed_datagrams_stay_bad() {
+        let tokens = Tokens::default();
+        tokens.recorded.lock().unwrap().insert(7, (addr(5000), Some([1u8; KEY_LEN])));
+        tokens.replayed.lock().unwrap().insert(addr(5000), (9, Some([2u8; KEY_LEN])));
+
+        let mut buf = vec![];
+        buf.extend(2i32.to_be_bytes());
+        buf.extend(7u32.to_be_bytes());
+        buf.extend([0u8; 20 + MAC_LEN]);
+
+        tokens.rewrite(&mut buf, false);
+        let len = buf.len() - MAC_LEN;
+        assert_eq!(u32::from_be_bytes([buf[4], buf[5], buf[6], buf[7]]), 9);
+        assert!(!mac::verify(&[2u8; KEY_LEN], &buf[..len], &buf[len..]));
+    }
+}
@@ -0,0 +1,199 @@
+use std::collections::{BTreeSet, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{mpsc, watch};
+use crate::client::calibration::Problem;
+use crate::client::calibration_store::Saved;
+use crate::client::downlink::Downlink;
+use crate::client::handshake::Claim;
+use crate::client::init::{InitData, InitPhase};
+use crate::client::{PosCoord, ShooterCoord};
+
+pub const RECONNECT_WINDOW: Duration = Duration::from_secs(30);
+pub const MAX_PLAYERS: u32 = 4;
+
+/*
+    Everything about a player that has to outlive its TCP connection,
+    so a controller that reconnects gets its calibration and channels back
+ */
+pub struct Session {
+    pub(super) index: u32,
+    pub(super) token: u32,
+    /* What saved calibrations are kept under, see calibration_store.rs */
+    pub(super) identity: Option<String>,
+    pub(super) init_data: InitData,
+    pub(super) shooter: ShooterCoord,
+    pub(super) calibrated: bool,
+    pub(super) next_phase_rx: watch::Receiver<Option<InitPhase>>,
+    pub(super) done_phase_tx: watch::Sender<Option<InitPhase>>,
+    pub(super) init_data_tx: watch::Sender<Option<InitData>>,
+    /* Every finished calibration, for main to report on */
+    pub(super) calibration_tx: watch::Sender<Option<InitData>>,
+    /* What was wrong with the last side points, if anything, see calibration::check */
+    pub(super) problem_tx: watch::Sender<Option<Problem>>,
+    pub(super) downlink_rx: mpsc::UnboundedReceiver<Downlink>,
+    /* For the connection's own messages, like pings */
+    pub(super) downlink_tx: mpsc::UnboundedSender<Downlink>,
+}
+
+/*
+    The game-side ends of a session's channels, handed to main when a player joins the lobby
+ */
+pub struct Player {
+    pub index: u32,
+    pub token: u32,
+    pub name: String,
+    pub pos_rx: watch::Receiver<PosCoord>,
+    pub next_phase_tx: watch::Sender<Option<InitPhase>>,
+    pub done_phase_rx: watch::Receiver<Option<InitPhase>>,
+    pub calibration_rx: watch::Receiver<Option<InitData>>,
+    pub problem_rx: watch::Receiver<Option<Problem>>,
+    pub downlink_tx: mpsc::UnboundedSender<Downlink>,
+}
+
+impl Session {
+    pub(super) fn open(
+        index: u32,
+        token: u32,
+        name: String,
+        identity: Option<String>,
+        window_size: (f32, f32),
+        init_data_tx: watch::Sender<Option<InitData>>,
+        pos_rx: watch::Receiver<PosCoord>,
+    ) -> (Session, Player) {
+        let (next_phase_tx, next_phase_rx) = watch::channel(None);
+        let (done_phase_tx, done_phase_rx) = watch::channel(None);
+        let (calibration_tx, calibration_rx) = watch::channel(None);
+        let (problem_tx, problem_rx) = watch::channel(None);
+        let (downlink_tx, downlink_rx) = mpsc::unbounded_channel();
+
+        let session = Session {
+            index,
+            token,
+            identity,
+            init_data: InitData::new(window_size),
+            shooter: (0.0, 0.0, 0.0),
+            calibrated: false,
+            next_phase_rx,
+            done_phase_tx,
+            init_data_tx,
+            calibration_tx,
+            problem_tx,
+            downlink_rx,
+            downlink_tx: downlink_tx.clone(),
+        };
+        let player = Player {
+            index,
+            token,
+            name,
+            pos_rx,
+            next_phase_tx,
+            done_phase_rx,
+            calibration_rx,
+            problem_rx,
+            downlink_tx,
+        };
+
+        (session, player)
+    }
+
+    /* Main sees it on calibration_rx and offers to keep it in the lobby */
+    pub(super) fn restore(&mut self, saved: Saved) {
+        self.init_data = saved.init_data;
+        self.shooter = saved.shooter;
+        self.calibrated = true;
+        self.init_data_tx.send(Some(self.init_data)).ok();
+        self.calibration_tx.send(Some(self.init_data)).ok();
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn token(&self) -> u32 {
+        self.token
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct ParkedSessions {
+    sessions: Arc<Mutex<HashMap<u32, (Session, Instant)>>>,
+}
+
+impl ParkedSessions {
+    pub fn new() -> Self {
+        Self {
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub(super) fn park(&self, session: Session) {
+        println!("Parking session of client {} for {}s", session.index, RECONNECT_WINDOW.as_secs());
+        self.sessions.lock().unwrap().insert(session.index, (session, Instant::now()));
+    }
+
+    /*
+        Expired sessions stay parked rather than dropped, as main still holds
+        the other ends of their phase channels
+     */
+    pub(super) fn claim(&self, claim: Claim) -> Option<Session> {
+        let mut sessions = self.sessions.lock().unwrap();
+
+        let index = match claim {
+            Claim::Index(index) => index,
+            Claim::Session(token) => *sessions.iter().find(|(_, (x, _))| x.token == token)?.0,
+        };
+        if sessions.get(&index)?.1.elapsed() >= RECONNECT_WINDOW {
+            return None;
+        }
+        sessions.remove(&index).map(|(x, _)| x)
+    }
+
+    pub fn contains(&self, index: u32) -> bool {
+        self.sessions.lock().unwrap().contains_key(&index)
+    }
+
+    /*
+        Only call this once main has dropped its ends of the session's channels.
+        Gives back the session token, so its stream can go too.
+     */
+    pub fn discard(&self, index: u32) -> Option<u32> {
+        let (session, _) = self.sessions.lock().unwrap().remove(&index)?;
+        println!("Discarded parked session of client {index}");
+        Some(session.token)
+    }
+}
+
+/*
+    Player indices in use, either by a connected player or a parked session.
+    Indices go into per-player arrays like the scoreboard, so they must stay below MAX_PLAYERS
+ */
+#[derive(Clone, Default)]
+pub struct Slots {
+    taken: Arc<Mutex<BTreeSet<u32>>>,
+}
+
+impl Slots {
+    /*
+        Hands out the wanted index if it's free, otherwise the lowest free one
+     */
+    pub(super) fn take(&self, wanted: u32) -> Option<u32> {
+        let mut taken = self.taken.lock().unwrap();
+
+        let index = if wanted < MAX_PLAYERS && !taken.contains(&wanted) {
+            wanted
+        } else {
+            (0..MAX_PLAYERS).find(|x| !taken.contains(x))?
+        };
+        taken.insert(index);
+        Some(index)
+    }
+
+    pub fn release(&self, index: u32) {
+        self.taken.lock().unwrap().remove(&index);
+    }
+
+    pub fn free(&self) -> u32 {
+        MAX_PLAYERS - self.taken.lock().unwrap().len() as u32
+    }
+}
@@ -0,0 +1,132 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+
+/* Controllers only ever send small messages, anything bigger is a misbehaving peer */
+const MAX_PAYLOAD: u64 = 4096;
+
+pub const TEXT: u8 = 1;
+pub const BINARY: u8 = 2;
+pub const CLOSE: u8 = 8;
+pub const PING: u8 = 9;
+pub const PONG: u8 = 10;
+
+pub struct Frame {
+    pub opcode: u8,
+    pub payload: Vec<u8>,
+}
+
+impl Frame {
+    pub fn text(text: String) -> Frame {
+        Frame { opcode: TEXT, payload: text.into_bytes() }
+    }
+
+    pub fn binary(payload: Vec<u8>) -> Frame {
+        Frame { opcode: BINARY, payload }
+    }
+
+    pub fn close() -> Frame {
+        Frame { opcode: CLOSE, payload: vec![] }
+    }
+
+    /*
+        Client frames are always masked. Browsers don't fragment messages this small,
+        so continuation frames are treated as a protocol error like the other malformed cases.
+     */
+    pub async fn read<R: AsyncRead + Unpin>(socket: &mut R) -> Option<Frame> {
+        let mut head = [0u8; 2];
+        socket.read_exact(&mut head).await.ok()?;
+
+        let fin = head[0] & 0x80 != 0;
+        let opcode = head[0] & 0x0f;
+        let masked = head[1] & 0x80 != 0;
+        if !fin || !masked {
+            return None;
+        }
+
+        let len = match head[1] & 0x7f {
+            126 => socket.read_u16().await.ok()? as u64,
+            127 => socket.read_u64().await.ok()?,
+            len => len as u64,
+        };
+        if len > MAX_PAYLOAD {
+            return None;
+        }
+
+        let mut mask = [0u8; 4];
+        socket.read_exact(&mut mask).await.ok()?;
+        let mut payload = vec![0u8; len as usize];
+        socket.read_exact(&mut payload).await.ok()?;
+        for (i, x) in payload.iter_mut().enumerate() {
+            *x ^= mask[i % 4];
+        }
+
+        Some(Frame { opcode, payload })
+    }
+
+    pub async fn write<W: AsyncWrite + Unpin>(&self, socket: &mut W) -> std::io::Result<()> {
+        let mut buf = vec![0x80 | self.opcode];
+
+        let len = self.payload.len();
+        if len < 126 {
+            buf.push(len as u8);
+        } else if len <= u16::MAX as usize {
+            buf.push(126);
+            buf.extend((len as u16).to_be_bytes());
+        } else {
+            buf.push(127);
+            buf.extend((len as u64).to_be_bytes());
+        }
+        buf.extend(&self.payload);
+
+        socket.write_all(&buf).await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn masked(head: &[u8], payload: &[u8]) -> Vec<u8> {
+        let mask = [0x37, 0xfa, 0x21, 0x3d];
+        let mut buf = head.to_vec();
+        buf.extend(mask);
+        buf.extend(payload.iter().enumerate().map(|(i, x)| x ^ mask[i % 4]));
+        buf
+    }
+
+    /* RFC 6455 section 5.7 */
+    #[tokio::test]
+    async fn reads_masked_frames() {
+        let frame = Frame::read(&mut &masked(&[0x81, 0x85], b"Hello")[..]).await.unwrap();
+        assert_eq!((frame.opcode, frame.payload), (TEXT, b"Hello".to_vec()));
+
+        let payload = vec![7u8; 300];
+        let frame = Frame::read(&mut &masked(&[0x82, 0xfe, 0x01, 0x2c], &payload)[..]).await.unwrap();
+        assert_eq!((frame.opcode, frame.payload), (BINARY, payload));
+    }
+
+    #[tokio::test]
+    async fn refuses_unmasked_fragmented_oversized_and_short_frames() {
+        assert!(Frame::read(&mut &[0x81, 0x05, b'H', b'e', b'l', b'l', b'o'][..]).await.is_none());
+        assert!(Frame::read(&mut &masked(&[0x01, 0x85], b"Hello")[..]).await.is_none());
+        let mut oversized = vec![0x82, 0xff];
+        oversized.extend((MAX_PAYLOAD + 1).to_be_bytes());
+        assert!(Frame::read(&mut &masked(&oversized, &[])[..]).await.is_none());
+        assert!(Frame::read(&mut &masked(&[0x81, 0x85], b"Hell")[..]).await.is_none());
+    }
+
+    #[tokio::test]
+    async fn writes_unmasked_frames() {
+        let mut buf = vec![];
+        Frame::text("Hello".to_string()).write(&mut buf).await.unwrap();
+        assert_eq!(buf, [0x81, 0x05, b'H', b'e', b'l', b'l', b'o']);
+
+        let mut buf = vec![];
+        Frame::binary(vec![0; 300]).write(&mut buf).await.unwrap();
+        assert_eq!(buf[..4], [0x82, 126, 0x01, 0x2c]);
+        assert_eq!(buf.len(), 304);
+
+        let mut buf = vec![];
+        Frame::binary(vec![0; 70000]).write(&mut buf).await.unwrap();
+        assert_eq!(buf[..10], [0x82, 127, 0, 0, 0, 0, 0, 0x01, 0x11, 0x70]);
+    }
+}
@@ -0,0 +1,123 @@
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use crate::client::mac::sha1;
+
+const MAX_REQUEST_LEN: usize = 8192;
+const WEBSOCKET_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+pub struct Request {
+    pub path: String,
+    headers: Vec<(String, String)>,
+}
+
+impl Request {
+    /*
+        Reads up to the blank line ending the headers. Requests here never have a body.
+     */
+    pub async fn read<R: AsyncRead + Unpin>(socket: &mut R) -> Option<Request> {
+        let mut buf = vec![];
+        while !buf.ends_with(b"\r\n\r\n") {
+            if buf.len() >= MAX_REQUEST_LEN {
+                return None;
+            }
+            buf.push(socket.read_u8().await.ok()?);
+        }
+
+        let text = String::from_utf8(buf).ok()?;
+        let mut lines = text.split("\r\n");
+
+        let mut request_line = lines.next()?.split(' ');
+        if request_line.next()? != "GET" {
+            return None;
+        }
+        let path = request_line.next()?.to_string();
+
+        let headers = lines
+            .filter_map(|x| x.split_once(':'))
+            .map(|(name, value)| (name.trim().to_ascii_lowercase(), value.trim().to_string()))
+            .collect();
+
+        Some(Request { path, headers })
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.iter().find(|(x, _)| x == name).map(|(_, x)| x.as_str())
+    }
+
+    pub fn websocket_key(&self) -> Option<&str> {
+        let upgrade = self.header("upgrade")?;
+        if !upgrade.eq_ignore_ascii_case("websocket") {
+            return None;
+        }
+        self.header("sec-websocket-key")
+    }
+}
+
+pub async fn switch_protocols<W: AsyncWrite + Unpin>(socket: &mut W, key: &str) -> std::io::Result<()> {
+    let accept = base64(&sha1(format!("{key}{WEBSOCKET_GUID}").as_bytes()));
+    let response = format!(
+        "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+    );
+    socket.write_all(response.as_bytes()).await
+}
+
+pub async fn respond<W: AsyncWrite + Unpin>(socket: &mut W, status: &str, content_type: &str, body: &str) -> std::io::Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    socket.write_all(response.as_bytes()).await?;
+    socket.shutdown().await
+}
+
+fn base64(data: &[u8]) -> String {
+    const ALPHABET: &[u8] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut ret = String::new();
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16 | (*chunk.get(1).unwrap_or(&0) as u32) << 8 | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                ret.push(ALPHABET[(n >> (18 - i * 6) & 0x3f) as usize] as char);
+            } else {
+                ret.push('=');
+            }
+        }
+    }
+    ret
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /* RFC 4648 section 10 */
+    #[test]
+    fn base64_known_answers() {
+        for (data, expected) in [("", ""), ("f", "Zg=="), ("fo", "Zm8="), ("foo", "Zm9v"), ("foob", "Zm9vYg=="), ("fooba", "Zm9vYmE="), ("foobar", "Zm9vYmFy")] {
+            assert_eq!(base64(data.as_bytes()), expected);
+        }
+    }
+
+    /* RFC 6455 section 1.3 */
+    #[tokio::test]
+    async fn accepts_the_sample_handshake() {
+        let text = b"GET /ws HTTP/1.1\r\nHost: x\r\nUpgrade: WebSocket\r\nSEC-WEBSOCKET-KEY:  dGhlIHNhbXBsZSBub25jZQ==\r\n\r\n";
+        let request = Request::read(&mut &text[..]).await.unwrap();
+        assert_eq!(request.path, "/ws");
+        assert_eq!(request.header("host"), Some("x"));
+
+        let mut buf = vec![];
+        switch_protocols(&mut buf, request.websocket_key().unwrap()).await.unwrap();
+        assert!(String::from_utf8(buf).unwrap().contains("\r\nSec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n"));
+    }
+
+    #[tokio::test]
+    async fn refuses_other_requests() {
+        assert!(Request::read(&mut &b"POST / HTTP/1.1\r\n\r\n"[..]).await.is_none());
+        assert!(Request::read(&mut &b"GET / HTTP/1.1\r\nHost: x\r\n"[..]).await.is_none());
+        assert!(Request::read(&mut &vec![b'a'; MAX_REQUEST_LEN + 4][..]).await.is_none());
+
+        let request = Request::read(&mut &b"GET / HTTP/1.1\r\nSec-WebSocket-Key: abc\r\n\r\n"[..]).await.unwrap();
+        assert!(request.websocket_key().is_none());
+    }
+}
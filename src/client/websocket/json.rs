@@ -0,0 +1,172 @@
+use std::collections::HashMap;
+use std::iter::Peekable;
+use std::str::Chars;
+
+/*
+    Just enough JSON for controller messages: one flat object whose values
+    are strings, numbers, booleans or arrays of numbers
+ */
+#[derive(Clone, Debug)]
+pub enum Value {
+    String(String),
+    Number(f64),
+    Array(Vec<f64>),
+}
+
+pub struct Object(HashMap<String, Value>);
+
+impl Object {
+    pub fn parse(text: &str) -> Option<Object> {
+        let mut chars = text.chars().peekable();
+        let mut ret = HashMap::new();
+
+        expect(&mut chars, '{')?;
+        skip_whitespace(&mut chars);
+        if chars.peek() == Some(&'}') {
+            chars.next();
+            return Some(Object(ret));
+        }
+
+        loop {
+            skip_whitespace(&mut chars);
+            let key = parse_string(&mut chars)?;
+            expect(&mut chars, ':')?;
+            skip_whitespace(&mut chars);
+            let value = match chars.peek()? {
+                '"' => Value::String(parse_string(&mut chars)?),
+                '[' => Value::Array(parse_array(&mut chars)?),
+                _ => Value::Number(parse_number(&mut chars)?),
+            };
+            ret.insert(key, value);
+
+            skip_whitespace(&mut chars);
+            match chars.next()? {
+                ',' => continue,
+                '}' => break,
+                _ => return None,
+            }
+        }
+
+        Some(Object(ret))
+    }
+
+    pub fn str(&self, key: &str) -> Option<&str> {
+        match self.0.get(key)? {
+            Value::String(x) => Some(x.as_str()),
+            _ => None,
+        }
+    }
+
+    pub fn number(&self, key: &str) -> Option<f64> {
+        match self.0.get(key)? {
+            Value::Number(x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    pub fn array(&self, key: &str) -> Option<&[f64]> {
+        match self.0.get(key)? {
+            Value::Array(x) => Some(x.as_slice()),
+            _ => None,
+        }
+    }
+}
+
+fn skip_whitespace(chars: &mut Peekable<Chars>) {
+    while chars.peek().is_some_and(|x| x.is_whitespace()) {
+        chars.next();
+    }
+}
+
+fn expect(chars: &mut Peekable<Chars>, c: char) -> Option<()> {
+    skip_whitespace(chars);
+    (chars.next()? == c).then_some(())
+}
+
+fn parse_string(chars: &mut Peekable<Chars>) -> Option<String> {
+    expect(chars, '"')?;
+
+    let mut ret = String::new();
+    loop {
+        match chars.next()? {
+            '"' => return Some(ret),
+            '\\' => match chars.next()? {
+                'n' => ret.push('\n'),
+                't' => ret.push('\t'),
+                'r' => ret.push('\r'),
+                'u' => {
+                    let hex: String = (0..4).filter_map(|_| chars.next()).collect();
+                    ret.push(char::from_u32(u32::from_str_radix(&hex, 16).ok()?).unwrap_or('\u{fffd}'));
+                }
+                c => ret.push(c),
+            },
+            c => ret.push(c),
+        }
+    }
+}
+
+fn parse_number(chars: &mut Peekable<Chars>) -> Option<f64> {
+    let mut text = String::new();
+    while chars.peek().is_some_and(|x| x.is_ascii_alphanumeric() || "+-.".contains(*x)) {
+        text.push(chars.next()?);
+    }
+
+    match text.as_str() {
+        "true" => Some(1.0),
+        "false" | "null" => Some(0.0),
+        _ => text.parse().ok(),
+    }
+}
+
+fn parse_array(chars: &mut Peekable<Chars>) -> Option<Vec<f64>> {
+    expect(chars, '[')?;
+
+    let mut ret = vec![];
+    skip_whitespace(chars);
+    if chars.peek() == Some(&']') {
+        chars.next();
+        return Some(ret);
+    }
+
+    loop {
+        skip_whitespace(chars);
+        ret.push(parse_number(chars)?);
+        skip_whitespace(chars);
+        match chars.next()? {
+            ',' => continue,
+            ']' => return Some(ret),
+            _ => return None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_flat_object() {
+        let object = Object::parse(r#" { "type" : "hello", "index":2, "name":"A \"b\"\né", "auth":true, "q":[1, -0.5,2e1] , "none":null }"#).unwrap();
+        assert_eq!(object.str("type"), Some("hello"));
+        assert_eq!(object.number("index"), Some(2.0));
+        assert_eq!(object.str("name"), Some("A \"b\"\né"));
+        assert_eq!(object.number("auth"), Some(1.0));
+        assert_eq!(object.array("q"), Some(&[1.0, -0.5, 20.0][..]));
+        assert_eq!(object.number("none"), Some(0.0));
+        assert_eq!(object.str("index"), None);
+        assert_eq!(object.number("missing"), None);
+    }
+
+    #[test]
+    fn parses_empty_containers() {
+        assert!(Object::parse("{}").is_some());
+        assert_eq!(Object::parse(r#"{"q": [ ]}"#).unwrap().array("q"), Some(&[][..]));
+    }
+
+    #[test]
+    fn refuses_malformed_text() {
+        for text in ["", "[]", "{", r#"{"a":1"#, r#"{"a" 1}"#, r#"{"a":1;}"#, r#"{a:1}"#, r#"{"a":"b}"#, r#"{"a":[1,}"#, r#"{"a":[1 2]}"#, r#"{"a":x}"#, r#"{"a":"\u12"}"#] {
+            assert!(Object::parse(text).is_none(), "{text}");
+        }
+    }
+}
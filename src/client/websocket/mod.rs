@@ -0,0 +1,305 @@
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc;
+use crate::client::datagram::{SESSION_ORIENTATION, SESSION_QUATERNION};
+use crate::client::downlink::{self, PHASE_WAIT_GRID_POINT};
+use crate::client::handshake::{self, Capabilities, PROTOCOL_VERSION};
+use crate::client::mac::{self, Key};
+use crate::client::raw_message::{self, HELLO, RESUME};
+use crate::client::recording::Recorded;
+use crate::client::websocket::frame::Frame;
+use crate::client::websocket::http::Request;
+use crate::client::websocket::json::Object;
+use crate::client::Context;
+
+mod frame;
+mod http;
+mod json;
+
+const CONTROLLER_PAGE: &str = include_str!("../../../res/controller.html");
+
+/*
+    Serves the browser controller page, and bridges WebSocket connections into the same
+    handshake and session handling as TCP controllers. Each socket gets an in-memory stream
+    speaking the TCP framing, while orientation updates are relayed as UDP datagrams.
+
+    Binary frames carry TCP frames as they are, except type 8: [4..8] sequence number,
+    [8..12] timestamp in ms, [12..28] w, x, y, z, the same orientation stream as UDP.
+    Text frames are flat JSON objects with a "type" of hello, resume, click, doubleclick,
+    hold, pong or orientation. Whichever the handshake used is also used for everything sent back.
+    The bridge asks for AUTH on the browser's behalf and signs what it relays, as a WebSocket
+    is as good a proof of who's aiming as a datagram key. The key never reaches the browser.
+ */
+pub async fn listen(listener: TcpListener, context: Context) {
+    println!("Controller page up at http://{}", listener.local_addr().unwrap());
+    loop {
+        let Ok((sock, addr)) = listener.accept().await else {
+            continue;
+        };
+        tokio::spawn(serve(sock, addr, context.clone()));
+    }
+}
+
+async fn serve(mut sock: TcpStream, addr: SocketAddr, context: Context) {
+    let Some(request) = Request::read(&mut sock).await else {
+        return;
+    };
+
+    if let Some(key) = request.websocket_key() {
+        if http::switch_protocols(&mut sock, key).await.is_ok() {
+            println!("WebSocket client {addr} connected");
+            bridge(sock, addr, context).await;
+        }
+    } else if request.path == "/" || request.path == "/index.html" {
+        http::respond(&mut sock, "200 OK", "text/html; charset=utf-8", CONTROLLER_PAGE).await.ok();
+    } else {
+        http::respond(&mut sock, "404 Not Found", "text/plain", "Not found").await.ok();
+    }
+}
+
+async fn bridge(sock: TcpStream, addr: SocketAddr, context: Context) {
+    let (mut ws_read, mut ws_write) = sock.into_split();
+
+    let (out_tx, mut out_rx) = mpsc::unbounded_channel::<Frame>();
+    tokio::spawn(async move {
+        while let Some(frame) = out_rx.recv().await {
+            let close = frame.opcode == frame::CLOSE;
+            if frame.write(&mut ws_write).await.is_err() || close {
+                break;
+            }
+        }
+    });
+
+    let (inner, outer) = tokio::io::duplex(1024);
+    match &context.recorder {
+        Some(recorder) => tokio::spawn(super::handle(Recorded::new(inner, addr, recorder.clone()), addr, context.clone())),
+        None => tokio::spawn(super::handle(inner, addr, context.clone())),
+    };
+    let (from_server, mut to_server) = tokio::io::split(outer);
+    let mut from_server = Some(from_server);
+
+    /* Session token and datagram key, known once the handshake is accepted. Orientation updates are dropped until then */
+    let session = Arc::new(Mutex::new(None::<(u32, Option<Key>)>));
+
+    while let Some(frame) = Frame::read(&mut ws_read).await {
+        let json = frame.opcode == frame::TEXT;
+        let uplink = match frame.opcode {
+            frame::TEXT => String::from_utf8(frame.payload).ok().and_then(|x| Object::parse(&x)).and_then(|x| Uplink::from_json(&x)),
+            frame::BINARY => Uplink::from_binary(&frame.payload),
+            frame::PING => {
+                out_tx.send(Frame { opcode: frame::PONG, payload: frame.payload }).ok();
+                continue;
+            }
+            frame::CLOSE => break,
+            _ => continue,
+        };
+        let Some(uplink) = uplink else {
+            println!("WebSocket client {addr} sent an unreadable message");
+            continue;
+        };
+
+        /* Replies start flowing after the first message, which also picks JSON or binary for them */
+        if let Some(mut from_server) = from_server.take() {
+            let session = session.clone();
+            let out_tx = out_tx.clone();
+            tokio::spawn(async move {
+                let mut buf = [0u8; 16];
+                while from_server.read_exact(&mut buf).await.is_ok() {
+                    if i32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) == 100 {
+                        let capabilities = Capabilities::from_bits(u16::from_be_bytes([buf[6], buf[7]]));
+                        let mut key = None;
+                        if capabilities.has(Capabilities::AUTH) {
+                            let mut buf = [0u8; mac::KEY_LEN];
+                            if from_server.read_exact(&mut buf).await.is_err() {
+                                break;
+                            }
+                            key = Some(buf);
+                        }
+                        buf[6..8].copy_from_slice(&(capabilities.bits() & !Capabilities::AUTH).to_be_bytes());
+                        *session.lock().unwrap() = Some((u32::from_be_bytes([buf[12], buf[13], buf[14], buf[15]]), key));
+                    }
+
+                    let frame = if json {
+                        let Some(text) = to_json(&buf) else {
+                            continue;
+                        };
+                        Frame::text(text)
+                    } else {
+                        Frame::binary(buf.to_vec())
+                    };
+                    out_tx.send(frame).ok();
+                }
+                /* The server ended the connection, a rejection or a silent client */
+                out_tx.send(Frame::close()).ok();
+            });
+        }
+
+        match uplink {
+            Uplink::Frame(buf) => {
+                if to_server.write_all(&buf).await.is_err() {
+                    break;
+                }
+            }
+            Uplink::Orientation { .. } => {
+                let session = *session.lock().unwrap();
+                if let Some((token, key)) = session {
+                    context.pos_man.feed(&uplink.datagram(token, key), addr);
+                }
+            }
+        }
+    }
+
+    println!("WebSocket client {addr} disconnected");
+    to_server.shutdown().await.ok();
+    out_tx.send(Frame::close()).ok();
+}
+
+enum Uplink {
+    Frame(Vec<u8>),
+    Orientation { seq: u32, timestamp: u32, values: Vec<f32> },
+}
+
+impl Uplink {
+    fn from_json(object: &Object) -> Option<Uplink> {
+        let message_type = object.str("type")?;
+
+        let mut buf = vec![];
+        match message_type {
+            "hello" | "resume" => {
+                let resume = message_type == "resume";
+                let claim = object.number(if resume { "session" } else { "index" })? as u32;
+                let version = object.number("version").map_or(PROTOCOL_VERSION, |x| x as u16);
+                let capabilities = object.number("capabilities").map_or(Capabilities::QUATERNION | Capabilities::HAPTICS, |x| x as u16) | Capabilities::AUTH;
+                let name = object.str("name").unwrap_or("");
+
+                buf.extend((if resume { RESUME } else { HELLO }).to_be_bytes());
+                buf.extend(version.to_be_bytes());
+                buf.extend(capabilities.to_be_bytes());
+                buf.extend(claim.to_be_bytes());
+                buf.extend((object.number("model").unwrap_or(0.0) as u16).to_be_bytes());
+                buf.extend((name.len() as u16).to_be_bytes());
+                buf.extend(name.as_bytes());
+                if version >= 2 {
+                    buf.extend((object.number("pin").unwrap_or(0.0) as u32).to_be_bytes());
+                }
+                if version >= 3 {
+                    let id = object.str("id").unwrap_or("");
+                    buf.extend((id.len() as u16).to_be_bytes());
+                    buf.extend(id.as_bytes());
+                }
+            }
+            "click" | "doubleclick" => {
+                let double = message_type == "doubleclick";
+                let timestamp = object.number("timestamp").map(|x| x as u32);
+                let (values, message_type) = match object.array("quaternion").filter(|x| x.len() == 4) {
+                    Some(q) => (q, if timestamp.is_some() { raw_message::TIMED_QUATERNION_CLICK } else { raw_message::QUATERNION_CLICK }),
+                    None => (object.array("ypr").filter(|x| x.len() == 3)?, if timestamp.is_some() { raw_message::TIMED_CLICK } else { raw_message::CLICK }),
+                };
+
+                buf.extend((message_type + double as i32).to_be_bytes());
+                if let Some(timestamp) = timestamp {
+                    buf.extend(timestamp.to_be_bytes());
+                }
+                values.iter().for_each(|x| buf.extend((*x as f32).to_be_bytes()));
+            }
+            "hold" => {
+                buf.extend(raw_message::HELD_DOUBLE_CLICK.to_be_bytes());
+                buf.extend([0u8; 12]);
+            }
+            "pong" => {
+                buf.extend(raw_message::PONG.to_be_bytes());
+                buf.extend((object.number("id")? as u32).to_be_bytes());
+                buf.extend((object.number("timestamp").unwrap_or(0.0) as u32).to_be_bytes());
+                buf.extend([0u8; 4]);
+            }
+            "orientation" => {
+                let values = object.array("quaternion").filter(|x| x.len() == 4)
+                    .or_else(|| object.array("ypr").filter(|x| x.len() == 3))?;
+                return Some(Uplink::Orientation {
+                    seq: object.number("seq")? as u32,
+                    timestamp: object.number("timestamp")? as u32,
+                    values: values.iter().map(|x| *x as f32).collect(),
+                });
+            }
+            _ => return None,
+        }
+
+        Some(Uplink::Frame(buf))
+    }
+
+    fn from_binary(payload: &[u8]) -> Option<Uplink> {
+        if payload.len() < 16 {
+            return None;
+        }
+        let message_type = i32::from_be_bytes([payload[0], payload[1], payload[2], payload[3]]);
+        if message_type == HELLO || message_type == RESUME {
+            let mut buf = payload.to_vec();
+            let capabilities = u16::from_be_bytes([buf[6], buf[7]]) | Capabilities::AUTH;
+            buf[6..8].copy_from_slice(&capabilities.to_be_bytes());
+            return Some(Uplink::Frame(buf));
+        }
+        if message_type != 8 {
+            return Some(Uplink::Frame(payload.to_vec()));
+        }
+        if payload.len() != 28 {
+            return None;
+        }
+
+        let word = |i: usize| [payload[i], payload[i + 1], payload[i + 2], payload[i + 3]];
+        Some(Uplink::Orientation {
+            seq: u32::from_be_bytes(word(4)),
+            timestamp: u32::from_be_bytes(word(8)),
+            values: (12..28).step_by(4).map(|i| f32::from_be_bytes(word(i))).collect(),
+        })
+    }
+
+    /*
+        Session orientation (type 2) or session quaternion (type 4) datagram, see datagram.rs
+     */
+    fn datagram(&self, token: u32, key: Option<Key>) -> Vec<u8> {
+        let Uplink::Orientation { seq, timestamp, values } = self else {
+            return vec![];
+        };
+
+        let mut buf = vec![];
+        buf.extend((if values.len() == 4 { SESSION_QUATERNION } else { SESSION_ORIENTATION }).to_be_bytes());
+        buf.extend(token.to_be_bytes());
+        buf.extend(seq.to_be_bytes());
+        buf.extend(timestamp.to_be_bytes());
+        values.iter().for_each(|x| buf.extend(x.to_be_bytes()));
+        if let Some(key) = key {
+            buf.extend(mac::sign(&key, &buf));
+        }
+        buf
+    }
+}
+
+/*
+    Mirrors HandshakeReply and Downlink framing
+ */
+fn to_json(buf: &[u8; 16]) -> Option<String> {
+    let word = |i: usize| [buf[i], buf[i + 1], buf[i + 2], buf[i + 3]];
+
+    let json = match i32::from_be_bytes(word(0)) {
+        handshake::ACCEPT => format!(
+            r#"{{"type":"accept","version":{},"capabilities":{},"index":{},"session":{}}}"#,
+            u16::from_be_bytes([buf[4], buf[5]]), u16::from_be_bytes([buf[6], buf[7]]),
+            u32::from_be_bytes(word(8)), u32::from_be_bytes(word(12))
+        ),
+        handshake::REJECT => format!(r#"{{"type":"reject","reason":{}}}"#, u32::from_be_bytes(word(8))),
+        downlink::HIT_CONFIRMED => r#"{"type":"hit"}"#.to_string(),
+        downlink::POINTS_GAINED => format!(r#"{{"type":"points","points":{}}}"#, i32::from_be_bytes(word(4))),
+        downlink::PHASE_CHANGED if u32::from_be_bytes(word(4)) == PHASE_WAIT_GRID_POINT => format!(
+            r#"{{"type":"phase","phase":{PHASE_WAIT_GRID_POINT},"point":{},"points":{}}}"#,
+            u32::from_be_bytes(word(8)), u32::from_be_bytes(word(12))
+        ),
+        downlink::PHASE_CHANGED => format!(r#"{{"type":"phase","phase":{}}}"#, u32::from_be_bytes(word(4))),
+        downlink::VIBRATE => format!(r#"{{"type":"vibrate","ms":{}}}"#, u32::from_be_bytes(word(4))),
+        downlink::LED_COLOR => format!(r#"{{"type":"led","color":[{},{},{}]}}"#, buf[4], buf[5], buf[6]),
+        downlink::PING => format!(r#"{{"type":"ping","id":{}}}"#, u32::from_be_bytes(word(4))),
+        _ => return None,
+    };
+    Some(json)
+}
@@ -2,18 +2,34 @@ use std::thread;
 use macroquad::prelude::*;
 use macroquad::Window;
 use mpsc::Sender;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
 use tokio::sync::watch;
 use std::sync::{Arc, mpsc};
 use macroquad::audio::play_sound_once;
 use crate::client::PosCoord;
+use crate::client::latency::LatencyStats;
+use crate::player_to_color;
 use crate::client::fake;
 use crate::game::object::{Depth, ObjectWrapper};
 use crate::sound::{SoundStore, SoundType};
 use crate::texture::TextureStore;
 
+/*
+    Crosshairs come and go with players: Some adds or replaces one, None removes it
+ */
+pub type CrosshairUpdate = (u32, Option<watch::Receiver<PosCoord>>);
+
+/*
+    What the display is told about the players: their crosshairs, who has gone silent and their latency
+ */
+pub struct PlayerFeeds {
+    pub crosshairs_rx: mpsc::Receiver<CrosshairUpdate>,
+    pub stale_rx: watch::Receiver<BTreeSet<u32>>,
+    pub latency_rx: watch::Receiver<BTreeMap<u32, LatencyStats>>,
+}
+
 pub fn launch(
-    pos_rxs: HashMap<u32, watch::Receiver<PosCoord>>,
+    players: PlayerFeeds,
     window_size: (f32, f32),
     fake_input_tx: Option<Sender<fake::RawMessage>>,
     objects_rx: watch::Receiver<Vec<ObjectWrapper>>,
@@ -34,13 +50,13 @@ pub fn launch(
                 icon: None,
                 platform: Default::default(),
             },
-            draw(pos_rxs, window_size, fake_input_tx, objects_rx, time_rx, bg_color_rx, sounds_rx)
+            draw(players, window_size, fake_input_tx, objects_rx, time_rx, bg_color_rx, sounds_rx)
         );
     });
 }
 
 async fn draw(
-    mut pos_rxs: HashMap<u32, watch::Receiver<PosCoord>>,
+    players: PlayerFeeds,
     window_size: (f32, f32),
     fake_input_tx: Option<Sender<fake::RawMessage>>,
     objects_rx: watch::Receiver<Vec<ObjectWrapper>>,
@@ -48,10 +64,13 @@ async fn draw(
     bg_color_rx: watch::Receiver<Color>,
     sounds_rx: mpsc::Receiver<SoundType>,
 ) {
+    let PlayerFeeds { crosshairs_rx, stale_rx, latency_rx } = players;
     let (width, height) = window_size;
+    let mut show_latency = false;
 
     let texture_store = Arc::new(TextureStore::new());
     let sound_store = SoundStore::new().await;
+    let mut pos_rxs: HashMap<u32, watch::Receiver<PosCoord>> = HashMap::new();
 
     loop {
         clear_background(bg_color_rx.borrow().to_owned());
@@ -70,6 +89,13 @@ async fn draw(
             }
         }
 
+        for (i, pos_rx) in crosshairs_rx.try_iter() {
+            match pos_rx {
+                Some(pos_rx) => { pos_rxs.insert(i, pos_rx); }
+                None => { pos_rxs.remove(&i); }
+            }
+        }
+
         while let Some(x) = sounds_rx.try_iter().next() {
             if let Some(sound) = sound_store.get(&x) {
                 play_sound_once(sound);
@@ -114,17 +140,39 @@ async fn draw(
             }
         }
 
+        let stale = stale_rx.borrow().clone();
         for (i, pos_rx) in &mut pos_rxs {
             let (x, y) = *pos_rx.borrow_and_update();
             let crosshair = texture_store.crosshair(*i as i32 % 4);
-            draw_texture_ex(crosshair, width / 2.0 + x - width / 48.0, height / 2.0 - y - height / 27.0, WHITE, DrawTextureParams {
+            let tint = if stale.contains(i) { Color::new(1.0, 1.0, 1.0, 0.3) } else { WHITE };
+            draw_texture_ex(crosshair, width / 2.0 + x - width / 48.0, height / 2.0 - y - height / 27.0, tint, DrawTextureParams {
                 dest_size: Some(Vec2 { x: width / 36.0, y: height / 20.25 }),
                 source: None, rotation: 0.0, flip_x: false, flip_y: false, pivot: None,
             });
         }
 
+        for (i, index) in stale.iter().enumerate() {
+            let text = format!("Player {} is not responding", index + 1);
+            draw_text(text.as_str(), 50.0, height - 50.0 - i as f32 * 50.0, 60.0, player_to_color(*index as usize));
+        }
+
         draw_text(format!("FPS: {:03}", get_fps()).as_str(), 50.0, 50.0, 80.0, if get_fps() < 60 { RED } else { BLACK });
 
+        /* F3 toggles the latency overlay, to check the access point before a match rather than after */
+        if is_key_pressed(KeyCode::F3) {
+            show_latency = !show_latency;
+        }
+        if show_latency {
+            let ms = |x: Option<std::time::Duration>| x.map_or(String::from("  ?"), |x| format!("{:3}", x.as_millis()));
+            for (i, (index, stats)) in latency_rx.borrow().iter().enumerate() {
+                let text = format!(
+                    "P{} RTT {}ms ±{}  UDP {}ms ±{}",
+                    index + 1, ms(stats.rtt), stats.rtt_jitter.as_millis(), ms(stats.udp_delay), stats.udp_jitter.as_millis()
+                );
+                draw_text(text.as_str(), 50.0, 110.0 + i as f32 * 40.0, 40.0, player_to_color(*index as usize));
+            }
+        }
+
         next_frame().await;
     }
 }
@@ -1,13 +1,16 @@
+use std::collections::BTreeMap;
 use std::sync::{Arc, mpsc};
 use macroquad::color::Color;
 use crate::client::Message;
-use crate::game::Game;
+use crate::game::{Game, rewound_tick};
 use crate::game::object::balloon::{Balloon, BalloonColor};
 use crate::game::object::{Object, ObjectWrapper};
 use crate::game::object::cloud::Cloud;
+use crate::game::object::recalibration_overlay::RecalibrationOverlay;
 use crate::game::object::scoreboard::{Scoreboard, ScoreboardObject};
 use crate::game::object::special_balloon::{SpecialBalloon, SpecialBalloonEffect};
 use crate::game::object::timer::Timer;
+use crate::client::downlink::DownlinkSender;
 use crate::sound::SoundType;
 use crate::wait_unwrap_and_map;
 
@@ -18,21 +21,24 @@ pub struct BalloonGame {
     scoreboard_was_updated: bool,
     scoreboard: Scoreboard,
     latest_scoreboard_object: ScoreboardObject,
+    /* Players recalibrating mid-match, each with their own overlay */
+    recalibrations: BTreeMap<u32, Arc<Box<dyn Object + Send + Sync>>>,
 }
 
 impl BalloonGame {
-    pub fn new(window_size: (f32, f32), player_count: u32, duration: u32) -> Self {
+    pub fn new(window_size: (f32, f32), players: Vec<u32>, duration: u32) -> Self {
         Self {
             window_size,
             objects: vec![Arc::new(Box::new(Timer::new(duration)))],
             objects_was_updated: false,
             scoreboard_was_updated: false,
-            scoreboard: Scoreboard::new(player_count),
-            latest_scoreboard_object: ScoreboardObject::new(0, window_size, player_count),
+            latest_scoreboard_object: ScoreboardObject::new(0, window_size, players.clone()),
+            scoreboard: Scoreboard::new(players),
+            recalibrations: BTreeMap::new(),
         }
     }
 
-    pub fn scores(&self) -> Vec<i32> {
+    pub fn scores(&self) -> Vec<(u32, i32)> {
         self.scoreboard.scores()
     }
 
@@ -129,16 +135,23 @@ impl Game for BalloonGame {
         }
     }
 
-    fn on_message(&mut self, client: u32, message: Message, time: u32, sound_tx: &mut mpsc::Sender<SoundType>) {
+    fn on_message(&mut self, client: u32, message: Message, time: u32, sound_tx: &mut mpsc::Sender<SoundType>, downlink_tx: &DownlinkSender) {
         match message {
-            Message::Click(pos) => {
+            Message::Click(pos, at) => {
+                /* Checked against where things were when the trigger was pulled, not where they are now */
+                let shot_time = rewound_tick(time, at);
                 let mut shooteds = vec![];
                 let mut i = 0;
                 while i < self.objects.len() {
-                    if let Some(object_pos) = self.objects[i].shoot_check(pos, time, self.window_size) {
+                    let shot = if self.objects[i].born_time() <= shot_time {
+                        self.objects[i].shoot_check(pos, shot_time, self.window_size)
+                    } else {
+                        None
+                    };
+                    if let Some(object_pos) = shot {
                         let x = self.objects.remove(i);
                         wait_unwrap_and_map(x, |mut x| {
-                            x.shoot(object_pos, time, client, &mut self.scoreboard, sound_tx);
+                            x.shoot(object_pos, time, client, &mut self.scoreboard, sound_tx, downlink_tx);
                             // this causes a scoreboard change, resulting in a object update
                             self.objects_was_updated = true;
                             self.scoreboard_was_updated = true;
@@ -152,6 +165,14 @@ impl Game for BalloonGame {
                     self.add_objects(i);
                 }
             },
+            Message::Recalibrating(phase, problem) => {
+                let overlay = RecalibrationOverlay::new(client, phase, problem.map(|x| x.explain()));
+                self.recalibrations.insert(client, Arc::new(Box::new(overlay)));
+                self.objects_was_updated = true;
+            }
+            Message::Recalibrated | Message::Disconnect if self.recalibrations.remove(&client).is_some() => {
+                self.objects_was_updated = true;
+            }
             _ => {
 
             }
@@ -160,7 +181,8 @@ impl Game for BalloonGame {
     }
 
     fn objects(&mut self, time: u32) -> Vec<ObjectWrapper> {
-        let mut ret: Vec<ObjectWrapper> = self.objects.iter().map(|x| ObjectWrapper::Weak(Arc::downgrade(x))).collect();
+        let mut ret: Vec<ObjectWrapper> = self.objects.iter().chain(self.recalibrations.values())
+            .map(|x| ObjectWrapper::Weak(Arc::downgrade(x))).collect();
         if self.was_scoreboard_updated() {
             let scoreboard_object = ScoreboardObject::from(&self.scoreboard, &self.latest_scoreboard_object, time, 150, self.window_size);
             ret.push(ObjectWrapper::Arc(Arc::new(Box::new(scoreboard_object.clone()))));
@@ -9,11 +9,12 @@ use crate::game::object::balloon::Balloon;
 use crate::game::object::cloud::Cloud;
 use crate::game::object::game_result::GameResult;
 use crate::player_to_balloon_color;
+use crate::client::downlink::DownlinkSender;
 use crate::sound::SoundType;
 
 pub struct BalloonResults {
     window_size: (f32, f32),
-    scores: Vec<i32>,
+    scores: Vec<(u32, i32)>,
     objects: Vec<Arc<Box<dyn Object + Send + Sync>>>,
     objects_was_updated: bool,
 }
@@ -32,14 +33,14 @@ impl BalloonResults {
 impl Game for BalloonResults {
     fn on_time(&mut self, time: u32) {
         if time % 20 == 0 && time < 300 {
-            let mut x: Vec<(usize, &i32)> = self.scores.iter().enumerate().collect();
-            x.sort_by(|(_, a), (_, b)| { (**b).partial_cmp(*a).unwrap() });
+            let mut x: Vec<(u32, i32)> = self.scores.clone();
+            x.sort_by(|(_, a), (_, b)| { (*b).partial_cmp(a).unwrap() });
             let mut winners = vec![];
             let mut max = -1;
             for (i, val) in x {
-                if *val >= max {
-                    max = *val;
-                    winners.push(i);
+                if val >= max {
+                    max = val;
+                    winners.push(i as usize);
                 } else {
                     break;
                 }
@@ -68,7 +69,7 @@ impl Game for BalloonResults {
         }
     }
 
-    fn on_message(&mut self, _client: u32, _message: Message, _time: u32, _sound_tx: &mut Sender<SoundType>) {}
+    fn on_message(&mut self, _client: u32, _message: Message, _time: u32, _sound_tx: &mut Sender<SoundType>, _downlink_tx: &DownlinkSender) {}
 
     fn objects(&mut self, _time: u32) -> Vec<ObjectWrapper> {
         self.objects.iter().map(|x| ObjectWrapper::Weak(Arc::downgrade(x))).collect()
@@ -0,0 +1,163 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use macroquad::color::Color;
+use crate::client::downlink::DownlinkSender;
+use crate::client::Message;
+use crate::game::Game;
+use crate::game::object::{Object, ObjectWrapper};
+use crate::game::object::lobby_board::{LobbyBoard, SavedCalibration};
+use crate::sound::SoundType;
+
+const START_DELAY: u32 = 300;
+
+struct LobbyPlayer {
+    name: String,
+    ready: bool,
+    present: bool,
+    saved: SavedCalibration,
+}
+
+pub struct Lobby {
+    players: BTreeMap<u32, LobbyPlayer>,
+    all_ready_since: Option<u32>,
+    countdown: Option<u32>,
+    pin: Option<u32>,
+    objects: Vec<Arc<Box<dyn Object + Send + Sync>>>,
+    objects_was_updated: bool,
+}
+
+impl Lobby {
+    pub fn new() -> Self {
+        Self {
+            players: BTreeMap::new(),
+            all_ready_since: None,
+            countdown: None,
+            pin: None,
+            objects: vec![],
+            objects_was_updated: true,
+        }
+    }
+
+    pub fn join(&mut self, index: u32, name: String, present: bool) {
+        let name = if name.is_empty() { format!("Player {}", index + 1) } else { name };
+        self.players.insert(index, LobbyPlayer { name, ready: false, present, saved: SavedCalibration::Missing });
+        self.all_ready_since = None;
+        self.objects_was_updated = true;
+    }
+
+    /* The player has a calibration from an earlier match or a saved one, readying up with a double-click keeps it */
+    pub fn offer_calibration(&mut self, index: u32) {
+        if let Some(player) = self.players.get_mut(&index) {
+            player.saved = SavedCalibration::Offered;
+            self.objects_was_updated = true;
+        }
+    }
+
+    /* Present players who chose to skip the tutorial */
+    pub fn keeping_calibration(&self) -> Vec<u32> {
+        self.players.iter().filter(|(_, x)| x.present && x.saved == SavedCalibration::Kept).map(|(i, _)| *i).collect()
+    }
+
+    pub fn show_pin(&mut self, pin: Option<u32>) {
+        self.pin = pin;
+        self.objects_was_updated = true;
+    }
+
+    pub fn players(&self) -> Vec<u32> {
+        self.players.iter().filter(|(_, x)| x.present).map(|(i, _)| *i).collect()
+    }
+
+    pub fn should_start(&self, time: u32) -> bool {
+        self.all_ready_since.is_some_and(|x| time >= x + START_DELAY)
+    }
+
+    fn all_ready(&self) -> bool {
+        let mut present = self.players.values().filter(|x| x.present).peekable();
+        present.peek().is_some() && present.all(|x| x.ready)
+    }
+}
+
+impl Default for Lobby {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Game for Lobby {
+    fn on_time(&mut self, time: u32) {
+        if !self.all_ready() {
+            self.all_ready_since = None;
+        } else if self.all_ready_since.is_none() {
+            self.all_ready_since = Some(time);
+        }
+
+        let countdown = self.all_ready_since.map(|x| (x + START_DELAY).saturating_sub(time) / 100 + 1);
+        if countdown != self.countdown {
+            self.countdown = countdown;
+            self.objects_was_updated = true;
+        }
+    }
+
+    fn on_message(&mut self, client: u32, message: Message, _time: u32, _sound_tx: &mut Sender<SoundType>, _downlink_tx: &DownlinkSender) {
+        let Some(player) = self.players.get_mut(&client) else {
+            return;
+        };
+
+        match message {
+            Message::Click(..) => {
+                if player.present {
+                    player.ready = !player.ready;
+                    if player.saved == SavedCalibration::Kept {
+                        player.saved = SavedCalibration::Offered;
+                    }
+                }
+            }
+            /* A double-click's first click has toggled ready already, so this one always ends up ready */
+            Message::DoubleClick(..) => {
+                if player.present && player.saved != SavedCalibration::Missing {
+                    player.ready = true;
+                    player.saved = SavedCalibration::Kept;
+                }
+            }
+            Message::Disconnect => {
+                player.present = false;
+                player.ready = false;
+            }
+            Message::Reconnect => {
+                player.present = true;
+            }
+            _ => {
+                return;
+            }
+        }
+        self.objects_was_updated = true;
+    }
+
+    fn objects(&mut self, _time: u32) -> Vec<ObjectWrapper> {
+        let mut ret: Vec<ObjectWrapper> = self.objects.iter().map(|x| ObjectWrapper::Weak(Arc::downgrade(x))).collect();
+        let players = self.players.iter()
+            .filter(|(_, x)| x.present)
+            .map(|(i, x)| (*i, x.name.clone(), x.ready, x.saved))
+            .collect();
+        ret.push(ObjectWrapper::Arc(Arc::new(Box::new(LobbyBoard::new(players, self.countdown, self.pin)))));
+        ret
+    }
+
+    fn add_objects(&mut self, object: Arc<Box<dyn Object + Send + Sync>>) {
+        self.objects.push(object);
+        self.objects_was_updated = true;
+    }
+
+    fn was_objects_updated(&mut self) -> bool {
+        if self.objects_was_updated {
+            self.objects_was_updated = false;
+            return true;
+        }
+        false
+    }
+
+    fn background_color(&self, _time: u32) -> Color {
+        Color::from_rgba(147, 169, 209, 0)
+    }
+}
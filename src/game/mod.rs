@@ -1,18 +1,38 @@
 use std::sync::{Arc, mpsc};
+use std::time::{Duration, Instant};
 use macroquad::color::Color;
 use crate::game::object::{Object, ObjectWrapper};
 use crate::client::Message;
+use crate::client::downlink::DownlinkSender;
 use crate::sound::SoundType;
 
 pub mod object;
 pub mod balloon_game;
 pub mod balloon_results;
+pub mod lobby;
 pub mod tutorial;
 
+/* Game time advances one per tick, and main paces its loop at this */
+pub const TICK: Duration = Duration::from_millis(10);
+/* Furthest back a shot gets checked, so a controller with a broken clock can't fire into the past */
+pub const MAX_REWIND: Duration = Duration::from_millis(250);
+
+/*
+    The tick a shot fired at the given moment landed in, rewound from the current one
+    by however long the shot took to get here, at most MAX_REWIND
+ */
+pub fn rewound_tick(time: u32, at: Option<Instant>) -> u32 {
+    let Some(at) = at else {
+        return time;
+    };
+    let lag = at.elapsed().min(MAX_REWIND);
+    time.saturating_sub((lag.as_millis() / TICK.as_millis()) as u32)
+}
+
 
 pub trait Game {
     fn on_time(&mut self, time: u32);
-    fn on_message(&mut self, client: u32, message: Message, time: u32, sound_tx: &mut mpsc::Sender<SoundType>);
+    fn on_message(&mut self, client: u32, message: Message, time: u32, sound_tx: &mut mpsc::Sender<SoundType>, downlink_tx: &DownlinkSender);
     fn objects(&mut self, time: u32) -> Vec<ObjectWrapper>;
     fn add_objects(&mut self, object: Arc<Box<dyn Object + Send + Sync>>);
     fn was_objects_updated(&mut self) -> bool;
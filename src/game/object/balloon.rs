@@ -2,6 +2,7 @@ use std::sync::{Arc, mpsc};
 use crate::game::object::{Coord, Depth};
 use macroquad::prelude::*;
 use crate::game::object::scoreboard::Scoreboard;
+use crate::client::downlink::{Downlink, DownlinkSender};
 use crate::sound::SoundType;
 use crate::texture::TextureStore;
 use super::Object;
@@ -128,11 +129,14 @@ impl Object for Balloon {
         }
     }
 
-    fn shoot(&mut self, coord: Coord, time: u32, client: u32, scoreboard: &mut Scoreboard, sound_tx: &mut mpsc::Sender<SoundType>) {
+    fn shoot(&mut self, coord: Coord, time: u32, client: u32, scoreboard: &mut Scoreboard, sound_tx: &mut mpsc::Sender<SoundType>, downlink_tx: &DownlinkSender) {
         if let None = self.shot_data {
             self.shot_data = Some((time, coord));
             self.shoot_points = scoreboard.update(client, self.shoot_points, time);
             sound_tx.send(SoundType::BalloonExplosion).ok();
+            downlink_tx.send((client, Downlink::HitConfirmed)).ok();
+            downlink_tx.send((client, Downlink::PointsGained(self.shoot_points))).ok();
+            downlink_tx.send((client, Downlink::Vibrate(80))).ok();
         }
     }
 
@@ -0,0 +1,75 @@
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use macroquad::prelude::*;
+use crate::draw_text_center_align;
+use crate::client::calibration::grid_targets;
+use crate::client::downlink::DownlinkSender;
+use crate::game::object::{Coord, Depth, Object};
+use crate::game::object::scoreboard::Scoreboard;
+use crate::sound::SoundType;
+use crate::texture::TextureStore;
+
+/*
+    One target of grid calibration, over a plain backdrop so the earlier instructions don't show through
+ */
+pub struct CalibrationTarget {
+    point: u8,
+    count: u8,
+}
+
+impl CalibrationTarget {
+    pub fn new(point: u8, count: u8) -> Self {
+        Self {
+            point,
+            count,
+        }
+    }
+}
+
+impl Object for CalibrationTarget {
+    fn draw(&self, center: Coord, _age: u32, window_size: (f32, f32), _texture_store: Arc<TextureStore>) {
+        let (w, h) = window_size;
+        let (x, y) = center;
+
+        draw_rectangle(0., 0., w, h, Color::from_rgba(147, 169, 209, 255));
+
+        let title = "Aim at the target and pull the trigger";
+        draw_text_center_align(title, w * 0.5 + 4., h * 0.08 + 4., h * 0.06, BLACK);
+        draw_text_center_align(title, w * 0.5, h * 0.08, h * 0.06, WHITE);
+        let progress = format!("{} of {}", self.point + 1, self.count);
+        draw_text_center_align(progress.as_str(), w * 0.5, h * 0.93, h * 0.05, WHITE);
+
+        draw_circle(x, y, 30.0, WHITE);
+        draw_circle(x, y, 20.0, GREEN);
+        draw_circle(x, y, 6.0, WHITE);
+    }
+
+    fn pos(&self, _age: u32, window_size: (f32, f32)) -> Coord {
+        let (w, h) = window_size;
+        let (x, y) = grid_targets(self.count, window_size).get(self.point as usize).copied().unwrap_or((0.0, 0.0));
+        (w / 2. + x, h / 2. - y)
+    }
+
+    fn depth(&self) -> Depth {
+        Depth::Main(6)
+    }
+
+    fn max_age(&self) -> Option<u32> {
+        None
+    }
+
+    fn born_time(&self) -> u32 {
+        0
+    }
+
+    fn shoot_check(&self, _coord: Coord, _time: u32, _window_size: (f32, f32)) -> Option<Coord> {
+        None
+    }
+
+    fn shoot(&mut self, _coord: Coord, _time: u32, _client: u32, _scoreboard: &mut Scoreboard, _sound_tx: &mut Sender<SoundType>, _downlink_tx: &DownlinkSender) {
+    }
+
+    fn can_be_cleaned(&self, _time: u32) -> bool {
+        false
+    }
+}
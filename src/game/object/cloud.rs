@@ -2,6 +2,7 @@ use std::sync::{Arc, mpsc};
 use macroquad::prelude::*;
 use crate::game::object::{Coord, Depth, Object};
 use crate::game::object::scoreboard::Scoreboard;
+use crate::client::downlink::DownlinkSender;
 use crate::sound::SoundType;
 use crate::texture::TextureStore;
 
@@ -54,7 +55,7 @@ impl Object for Cloud {
         None
     }
 
-    fn shoot(&mut self, _coord: Coord, _time: u32, _client: u32, _scoreboard: &mut Scoreboard, _sound_tx: &mut mpsc::Sender<SoundType>) {
+    fn shoot(&mut self, _coord: Coord, _time: u32, _client: u32, _scoreboard: &mut Scoreboard, _sound_tx: &mut mpsc::Sender<SoundType>, _downlink_tx: &DownlinkSender) {
     }
 
     fn can_be_cleaned(&self, time: u32) -> bool {
@@ -3,6 +3,7 @@ use std::sync::mpsc::Sender;
 use macroquad::prelude::*;
 use crate::game::object::{Coord, Depth, Object};
 use crate::game::object::scoreboard::Scoreboard;
+use crate::client::downlink::DownlinkSender;
 use crate::sound::SoundType;
 use crate::texture::TextureStore;
 
@@ -48,7 +49,7 @@ impl Object for FullScreenImage {
         None
     }
 
-    fn shoot(&mut self, _coord: Coord, _time: u32, _client: u32, _scoreboard: &mut Scoreboard, _sound_tx: &mut Sender<SoundType>) {
+    fn shoot(&mut self, _coord: Coord, _time: u32, _client: u32, _scoreboard: &mut Scoreboard, _sound_tx: &mut Sender<SoundType>, _downlink_tx: &DownlinkSender) {
     }
 
     fn can_be_cleaned(&self, _time: u32) -> bool {
@@ -0,0 +1,116 @@
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use macroquad::prelude::*;
+use crate::{draw_text_center_align, player_to_color};
+use crate::client::downlink::DownlinkSender;
+use crate::game::object::{Coord, Depth, Object};
+use crate::game::object::scoreboard::Scoreboard;
+use crate::sound::SoundType;
+use crate::texture::TextureStore;
+
+/* Whether a player has a calibration from earlier to keep, and if they're keeping it */
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum SavedCalibration {
+    Missing,
+    Offered,
+    Kept,
+}
+
+#[derive(Clone)]
+pub struct LobbyBoard {
+    players: Vec<(u32, String, bool, SavedCalibration)>,
+    countdown: Option<u32>,
+    pin: Option<u32>,
+}
+
+impl LobbyBoard {
+    pub fn new(players: Vec<(u32, String, bool, SavedCalibration)>, countdown: Option<u32>, pin: Option<u32>) -> Self {
+        Self {
+            players,
+            countdown,
+            pin,
+        }
+    }
+}
+
+impl Object for LobbyBoard {
+    fn draw(&self, center: Coord, _age: u32, window_size: (f32, f32), _texture_store: Arc<TextureStore>) {
+        let (w, h) = window_size;
+        let (x, y) = center;
+
+        let title = if self.players.is_empty() { "Waiting for players" } else { "Pull the trigger when you're ready" };
+        draw_text_center_align(title, x + 5., h * 0.15 + 5., h * 0.08, BLACK);
+        draw_text_center_align(title, x, h * 0.15, h * 0.08, WHITE);
+
+        if self.players.iter().any(|x| x.3 != SavedCalibration::Missing) {
+            let text = "Double-click to keep your calibration and skip the tutorial";
+            draw_text_center_align(text, x + 3., h * 0.75 + 3., h * 0.045, BLACK);
+            draw_text_center_align(text, x, h * 0.75, h * 0.045, WHITE);
+        }
+
+        if let Some(pin) = self.pin {
+            let text = format!("PIN {:04}", pin);
+            draw_text_center_align(text.as_str(), x + 4., h * 0.25 + 4., h * 0.07, BLACK);
+            draw_text_center_align(text.as_str(), x, h * 0.25, h * 0.07, YELLOW);
+        }
+
+        let count = self.players.len() as f32;
+        for (i, (index, name, ready, saved)) in self.players.iter().enumerate() {
+            let card_x = w * (i as f32 + 1.) / (count + 1.) - w * 0.09;
+            let card_y = y - h * 0.15;
+
+            draw_rectangle(card_x + 5., card_y + 5., w * 0.18, h * 0.3, BLACK);
+            draw_rectangle(card_x, card_y, w * 0.18, h * 0.3, player_to_color(*index as usize));
+            draw_text_center_align(name.as_str(), card_x + w * 0.09, card_y + h * 0.1, h * 0.06, WHITE);
+
+            let status = if *ready { "READY" } else { "..." };
+            draw_text_center_align(status, card_x + w * 0.09, card_y + h * 0.22, h * 0.08, WHITE);
+
+            let saved = match saved {
+                SavedCalibration::Missing => None,
+                SavedCalibration::Offered => Some("saved calibration"),
+                SavedCalibration::Kept => Some("keeping calibration"),
+            };
+            if let Some(saved) = saved {
+                draw_text_center_align(saved, card_x + w * 0.09, card_y + h * 0.28, h * 0.035, WHITE);
+            }
+        }
+
+        /* The web listener is plain HTTP and phone browsers keep orientation to HTTPS pages */
+        let text = "Browser controllers need an HTTPS proxy in front of the web port, see the README";
+        draw_text_center_align(text, x + 2., h * 0.96 + 2., h * 0.03, BLACK);
+        draw_text_center_align(text, x, h * 0.96, h * 0.03, WHITE);
+
+        if let Some(countdown) = self.countdown {
+            let text = format!("Starting in {}", countdown);
+            draw_text_center_align(text.as_str(), x + 5., h * 0.85 + 5., h * 0.1, BLACK);
+            draw_text_center_align(text.as_str(), x, h * 0.85, h * 0.1, WHITE);
+        }
+    }
+
+    fn pos(&self, _age: u32, window_size: (f32, f32)) -> Coord {
+        (window_size.0 * 0.5, window_size.1 * 0.5)
+    }
+
+    fn depth(&self) -> Depth {
+        Depth::Foreground(0)
+    }
+
+    fn max_age(&self) -> Option<u32> {
+        None
+    }
+
+    fn born_time(&self) -> u32 {
+        0
+    }
+
+    fn shoot_check(&self, _coord: Coord, _time: u32, _window_size: (f32, f32)) -> Option<Coord> {
+        None
+    }
+
+    fn shoot(&mut self, _coord: Coord, _time: u32, _client: u32, _scoreboard: &mut Scoreboard, _sound_tx: &mut Sender<SoundType>, _downlink_tx: &DownlinkSender) {}
+
+    fn can_be_cleaned(&self, _time: u32) -> bool {
+        false
+    }
+}
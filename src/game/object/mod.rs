@@ -1,6 +1,7 @@
 use std::cmp::Ordering;
 use std::sync::{Arc, mpsc, Weak};
 use crate::game::object::scoreboard::Scoreboard;
+use crate::client::downlink::DownlinkSender;
 use crate::sound::SoundType;
 use crate::texture::TextureStore;
 
@@ -13,6 +14,11 @@ pub mod game_result;
 pub mod full_screen_image;
 pub mod correction_circle;
 pub mod init_indicator;
+pub mod lobby_board;
+pub mod calibration_target;
+pub mod residual_board;
+pub mod problem_board;
+pub mod recalibration_overlay;
 
 type Coord = (f32, f32);
 
@@ -23,7 +29,7 @@ pub trait Object {
     fn max_age(&self) -> Option<u32>;
     fn born_time(&self) -> u32;
     fn shoot_check(&self, coord: Coord, time: u32, window_size: (f32, f32)) -> Option<Coord>;
-    fn shoot(&mut self, coord: Coord, time: u32, client: u32, scoreboard: &mut Scoreboard, sound_tx: &mut mpsc::Sender<SoundType>);
+    fn shoot(&mut self, coord: Coord, time: u32, client: u32, scoreboard: &mut Scoreboard, sound_tx: &mut mpsc::Sender<SoundType>, downlink_tx: &DownlinkSender);
     fn can_be_cleaned(&self, time: u32) -> bool;
 }
 
@@ -0,0 +1,64 @@
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use macroquad::prelude::*;
+use crate::{draw_text_center_align, player_to_color};
+use crate::client::downlink::DownlinkSender;
+use crate::game::object::{Coord, Depth, Object};
+use crate::game::object::scoreboard::Scoreboard;
+use crate::sound::SoundType;
+use crate::texture::TextureStore;
+
+/*
+    Players whose calibration was turned down, and what they should do differently, one line each
+ */
+pub struct ProblemBoard {
+    problems: Vec<(u32, &'static str)>,
+}
+
+impl ProblemBoard {
+    pub fn new(problems: Vec<(u32, &'static str)>) -> Self {
+        Self {
+            problems,
+        }
+    }
+}
+
+impl Object for ProblemBoard {
+    fn draw(&self, center: Coord, _age: u32, window_size: (f32, f32), _texture_store: Arc<TextureStore>) {
+        let (_, h) = window_size;
+        let (x, y) = center;
+
+        for (i, (index, problem)) in self.problems.iter().enumerate() {
+            let text = format!("P{}: {}", index + 1, problem);
+            let line_y = y + i as f32 * h * 0.06;
+            draw_text_center_align(text.as_str(), x + 3., line_y + 3., h * 0.045, BLACK);
+            draw_text_center_align(text.as_str(), x, line_y, h * 0.045, player_to_color(*index as usize));
+        }
+    }
+
+    fn pos(&self, _age: u32, window_size: (f32, f32)) -> Coord {
+        (window_size.0 * 0.5, window_size.1 * 0.8)
+    }
+
+    fn depth(&self) -> Depth {
+        Depth::Foreground(0)
+    }
+
+    fn max_age(&self) -> Option<u32> {
+        None
+    }
+
+    fn born_time(&self) -> u32 {
+        0
+    }
+
+    fn shoot_check(&self, _coord: Coord, _time: u32, _window_size: (f32, f32)) -> Option<Coord> {
+        None
+    }
+
+    fn shoot(&mut self, _coord: Coord, _time: u32, _client: u32, _scoreboard: &mut Scoreboard, _sound_tx: &mut Sender<SoundType>, _downlink_tx: &DownlinkSender) {}
+
+    fn can_be_cleaned(&self, _time: u32) -> bool {
+        false
+    }
+}
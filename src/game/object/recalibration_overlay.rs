@@ -0,0 +1,90 @@
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use macroquad::prelude::*;
+use crate::{draw_text_center_align, player_to_color};
+use crate::client::downlink::DownlinkSender;
+use crate::client::init::InitPhase;
+use crate::client::session::MAX_PLAYERS;
+use crate::game::object::{Coord, Depth, Object};
+use crate::game::object::scoreboard::Scoreboard;
+use crate::sound::SoundType;
+use crate::texture::TextureStore;
+
+/*
+    A small panel along the bottom, in the player's own slot, walking them through recalibrating
+    while the others keep shooting. The side circles are drawn as rings so balloons still show through.
+ */
+pub struct RecalibrationOverlay {
+    index: u32,
+    phase: InitPhase,
+    problem: Option<&'static str>,
+}
+
+impl RecalibrationOverlay {
+    pub fn new(index: u32, phase: InitPhase, problem: Option<&'static str>) -> Self {
+        Self {
+            index,
+            phase,
+            problem,
+        }
+    }
+}
+
+impl Object for RecalibrationOverlay {
+    fn draw(&self, center: Coord, _age: u32, window_size: (f32, f32), _texture_store: Arc<TextureStore>) {
+        let (w, h) = window_size;
+        let (x, y) = center;
+        let color = player_to_color(self.index as usize);
+        let (panel_w, panel_h) = (w / MAX_PLAYERS as f32 * 0.9, h * 0.14);
+
+        draw_rectangle(x - panel_w / 2. + 4., y - panel_h / 2. + 4., panel_w, panel_h, BLACK);
+        draw_rectangle(x - panel_w / 2., y - panel_h / 2., panel_w, panel_h, color);
+
+        let step = match self.phase {
+            InitPhase::WaitMonitor => "Point straight at the screen",
+            InitPhase::WaitFirstPoint => "Point at your left ring",
+            _ => "Point at your right ring",
+        };
+        draw_text_center_align(format!("P{} recalibrating", self.index + 1).as_str(), x, y - panel_h * 0.25, h * 0.035, WHITE);
+        draw_text_center_align(step, x, y + panel_h * 0.05, h * 0.03, WHITE);
+        if let Some(problem) = self.problem {
+            draw_text_center_align(problem, x, y + panel_h * 0.32, h * 0.02, WHITE);
+        }
+
+        let ring = match self.phase {
+            InitPhase::WaitFirstPoint => Some(w / 2. - h / 2.),
+            InitPhase::WaitSecondPoint => Some(w / 2. + h / 2.),
+            _ => None,
+        };
+        if let Some(ring_x) = ring {
+            draw_circle_lines(ring_x, h / 2., 20., 4., color);
+        }
+    }
+
+    fn pos(&self, _age: u32, window_size: (f32, f32)) -> Coord {
+        let (w, h) = window_size;
+        (w * (self.index as f32 + 0.5) / MAX_PLAYERS as f32, h * 0.88)
+    }
+
+    fn depth(&self) -> Depth {
+        Depth::Foreground(0)
+    }
+
+    fn max_age(&self) -> Option<u32> {
+        None
+    }
+
+    fn born_time(&self) -> u32 {
+        0
+    }
+
+    fn shoot_check(&self, _coord: Coord, _time: u32, _window_size: (f32, f32)) -> Option<Coord> {
+        None
+    }
+
+    fn shoot(&mut self, _coord: Coord, _time: u32, _client: u32, _scoreboard: &mut Scoreboard, _sound_tx: &mut Sender<SoundType>, _downlink_tx: &DownlinkSender) {}
+
+    fn can_be_cleaned(&self, _time: u32) -> bool {
+        false
+    }
+}
@@ -0,0 +1,69 @@
+use std::sync::Arc;
+use std::sync::mpsc::Sender;
+use macroquad::prelude::*;
+use crate::{draw_text_center_align, player_to_color};
+use crate::client::downlink::DownlinkSender;
+use crate::game::object::{Coord, Depth, Object};
+use crate::game::object::scoreboard::Scoreboard;
+use crate::sound::SoundType;
+use crate::texture::TextureStore;
+
+/*
+    How well each player's grid calibration fit, None for one that couldn't be fitted at all
+ */
+pub struct ResidualBoard {
+    residuals: Vec<(u32, Option<f32>)>,
+}
+
+impl ResidualBoard {
+    pub fn new(residuals: Vec<(u32, Option<f32>)>) -> Self {
+        Self {
+            residuals,
+        }
+    }
+}
+
+impl Object for ResidualBoard {
+    fn draw(&self, center: Coord, _age: u32, window_size: (f32, f32), _texture_store: Arc<TextureStore>) {
+        let (w, h) = window_size;
+        let (_, y) = center;
+
+        let count = self.residuals.len() as f32;
+        for (i, (index, residual)) in self.residuals.iter().enumerate() {
+            let x = w * (i as f32 + 1.) / (count + 1.);
+            let text = match residual {
+                Some(residual) => format!("P{} off by {:.0}px", index + 1, residual),
+                None => format!("P{} couldn't fit", index + 1),
+            };
+            draw_text_center_align(text.as_str(), x + 3., y + 3., h * 0.05, BLACK);
+            draw_text_center_align(text.as_str(), x, y, h * 0.05, player_to_color(*index as usize));
+        }
+    }
+
+    fn pos(&self, _age: u32, window_size: (f32, f32)) -> Coord {
+        (window_size.0 * 0.5, window_size.1 * 0.9)
+    }
+
+    fn depth(&self) -> Depth {
+        Depth::Foreground(0)
+    }
+
+    fn max_age(&self) -> Option<u32> {
+        None
+    }
+
+    fn born_time(&self) -> u32 {
+        0
+    }
+
+    fn shoot_check(&self, _coord: Coord, _time: u32, _window_size: (f32, f32)) -> Option<Coord> {
+        None
+    }
+
+    fn shoot(&mut self, _coord: Coord, _time: u32, _client: u32, _scoreboard: &mut Scoreboard, _sound_tx: &mut Sender<SoundType>, _downlink_tx: &DownlinkSender) {
+    }
+
+    fn can_be_cleaned(&self, _time: u32) -> bool {
+        false
+    }
+}
@@ -2,18 +2,26 @@ use std::sync::{Arc, mpsc};
 use macroquad::prelude::*;
 use crate::{draw_text_center_align, player_to_color};
 use crate::game::object::{Coord, Depth, Object};
+use crate::client::downlink::DownlinkSender;
+use crate::client::session::MAX_PLAYERS;
 use crate::sound::SoundType;
 use crate::texture::TextureStore;
 
+/*
+    Scores are kept per index, and only the players of the match are reported,
+    as indices needn't be contiguous
+ */
 pub struct Scoreboard {
+    players: Vec<u32>,
     scores: Vec<i32>,
     multiplications: Vec<(i32, u32, u32)>,
 }
 
 impl Scoreboard {
-    pub fn new(client_count: u32) -> Self {
+    pub fn new(players: Vec<u32>) -> Self {
         Self {
-            scores: vec![0; 4],
+            players,
+            scores: vec![0; MAX_PLAYERS as usize],
             multiplications: vec![]
         }
     }
@@ -37,8 +45,9 @@ impl Scoreboard {
         self.multiplications.push((by, client, until));
     }
 
-    pub fn scores(&self) -> Vec<i32> {
-        self.scores.clone()
+    /* Each player with their score, in the order they were given */
+    pub fn scores(&self) -> Vec<(u32, i32)> {
+        self.players.iter().map(|x| (*x, self.scores[*x as usize])).collect()
     }
 }
 
@@ -47,6 +56,7 @@ impl Scoreboard {
  */
 #[derive(Clone)]
 pub struct ScoreboardObject {
+    players: Vec<u32>,
     birth_time: u32,
     start_state: Vec<f32>,
     target_state: Vec<f32>,
@@ -55,25 +65,27 @@ pub struct ScoreboardObject {
 }
 
 impl ScoreboardObject {
-    pub fn new(time: u32, window_size: (f32, f32), client_count: u32) -> Self {
-        let portion = window_size.0 / client_count as f32;
+    pub fn new(time: u32, window_size: (f32, f32), players: Vec<u32>) -> Self {
+        let portion = window_size.0 / players.len().max(1) as f32;
         Self {
             birth_time: time,
-            start_state: vec![portion; client_count as usize],
-            target_state: vec![portion; client_count as usize],
+            start_state: vec![portion; players.len()],
+            target_state: vec![portion; players.len()],
             animation_duration: 0,
-            scores: vec![0; client_count as usize]
+            scores: vec![0; players.len()],
+            players,
         }
     }
 
     pub fn from(scoreboard: &Scoreboard, previous: &ScoreboardObject, time: u32, animation_duration: u32, window_size: (f32, f32)) -> Self {
-        let scores = scoreboard.scores();
+        let (players, scores): (Vec<u32>, Vec<i32>) = scoreboard.scores().into_iter().unzip();
         let mut sum = 0;
         for i in &scores {
             sum += i;
         }
 
         Self {
+            players,
             birth_time: time,
             start_state: previous.current_state(time).unwrap(),
             target_state: scores.iter().map(|x| if sum == 0 {1. / scores.len() as f32} else {*x as f32 / sum as f32} * window_size.0).collect(),
@@ -101,7 +113,7 @@ impl Object for ScoreboardObject {
     fn draw(&self, _center: Coord, age: u32, window_size: (f32, f32), _texture_store: Arc<TextureStore>) {
         let mut sum = 0.;
         for (i, val) in self.current_state(age).unwrap().iter().enumerate() {
-            let color = player_to_color(i);
+            let color = player_to_color(self.players[i] as usize);
             draw_rectangle(sum, 0., *val, window_size.1 / 24.0, color);
             draw_text_center_align(self.scores[i].to_string().as_str(), sum + *val / 2., window_size.1 / 48.0, window_size.1 / 18.0, WHITE);
             sum += *val;
@@ -126,7 +138,7 @@ impl Object for ScoreboardObject {
         None
     }
 
-    fn shoot(&mut self, _coord: Coord, _time: u32, _client: u32, _scoreboard: &mut Scoreboard, _sound_tx: &mut mpsc::Sender<SoundType>) {}
+    fn shoot(&mut self, _coord: Coord, _time: u32, _client: u32, _scoreboard: &mut Scoreboard, _sound_tx: &mut mpsc::Sender<SoundType>, _downlink_tx: &DownlinkSender) {}
 
     fn can_be_cleaned(&self, _time: u32) -> bool {
         false
@@ -3,6 +3,7 @@ use std::sync::mpsc::Sender;
 use crate::game::object::balloon::{Balloon, BalloonColor};
 use crate::game::object::{Coord, Depth, Object};
 use crate::game::object::scoreboard::Scoreboard;
+use crate::client::downlink::DownlinkSender;
 use crate::sound::SoundType;
 use crate::texture::TextureStore;
 
@@ -94,8 +95,8 @@ impl Object for SpecialBalloon {
         }
     }
 
-    fn shoot(&mut self, coord: Coord, time: u32, client: u32, scoreboard: &mut Scoreboard, sound_tx: &mut Sender<SoundType>) {
-        self.base.shoot(coord, time, client, scoreboard, sound_tx);
+    fn shoot(&mut self, coord: Coord, time: u32, client: u32, scoreboard: &mut Scoreboard, sound_tx: &mut Sender<SoundType>, downlink_tx: &DownlinkSender) {
+        self.base.shoot(coord, time, client, scoreboard, sound_tx, downlink_tx);
 
         // if let SpecialBalloonEffect::MultiplyScore(by, duration) = self.effect {
         let SpecialBalloonEffect::MultiplyScore(by, duration) = self.effect;
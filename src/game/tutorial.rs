@@ -6,9 +6,13 @@ use crate::client::init::InitPhase;
 use crate::client::Message;
 use crate::game::Game;
 use crate::game::object::{Object, ObjectWrapper};
+use crate::game::object::calibration_target::CalibrationTarget;
 use crate::game::object::correction_circle::CorrectionCircle;
 use crate::game::object::full_screen_image::FullScreenImage;
 use crate::game::object::init_indicator::InitIndicator;
+use crate::game::object::problem_board::ProblemBoard;
+use crate::game::object::residual_board::ResidualBoard;
+use crate::client::downlink::DownlinkSender;
 use crate::sound::SoundType;
 
 pub struct Tutorial {
@@ -20,6 +24,12 @@ pub struct Tutorial {
     last_init_indicator: InitIndicator,
     init_state: HashMap<i32, bool>,
     init_state_was_updated: bool,
+    /* Only the current grid target is shown, each one replaces the last */
+    grid_target: Option<Arc<Box<dyn Object + Send + Sync>>>,
+    residuals: Option<Arc<Box<dyn Object + Send + Sync>>>,
+    problems: Option<Arc<Box<dyn Object + Send + Sync>>>,
+    /* Where the side point steps' objects start, so a redo can take them down again */
+    side_points_at: Option<usize>,
 }
 
 impl Tutorial {
@@ -33,6 +43,10 @@ impl Tutorial {
             last_init_indicator: InitIndicator::new(HashMap::new()),
             init_state: HashMap::new(),
             init_state_was_updated: true,
+            grid_target: None,
+            residuals: None,
+            problems: None,
+            side_points_at: None,
         }
     }
 
@@ -55,6 +69,17 @@ impl Tutorial {
         self.init_state_was_updated = true;
         self.objects_was_updated = true;
     }
+
+    pub fn show_residuals(&mut self, residuals: Vec<(u32, Option<f32>)>) {
+        self.residuals = Some(Arc::new(Box::new(ResidualBoard::new(residuals))));
+        self.objects_was_updated = true;
+    }
+
+    /* Stays up while those players redo their side points, an empty list takes it down */
+    pub fn show_problems(&mut self, problems: Vec<(u32, &'static str)>) {
+        self.problems = (!problems.is_empty()).then(|| Arc::new(Box::new(ProblemBoard::new(problems)) as Box<dyn Object + Send + Sync>));
+        self.objects_was_updated = true;
+    }
 }
 
 impl Game for Tutorial {
@@ -69,6 +94,8 @@ impl Game for Tutorial {
                     }
                     InitPhase::WaitFirstPoint => {
                         println!("Waitfp");
+                        let at = *self.side_points_at.get_or_insert(self.objects.len());
+                        self.objects.truncate(at);
                         self.add_objects(Arc::new(Box::new(FullScreenImage::new(2, 1))));
                         self.add_objects(Arc::new(Box::new(CorrectionCircle::new(true, 2))));
                     }
@@ -77,8 +104,14 @@ impl Game for Tutorial {
                         self.add_objects(Arc::new(Box::new(FullScreenImage::new(3, 3))));
                         self.add_objects(Arc::new(Box::new(CorrectionCircle::new(false, 4))));
                     }
+                    InitPhase::WaitGridPoint { index, count } => {
+                        println!("Waitgrid {index}");
+                        self.grid_target = Some(Arc::new(Box::new(CalibrationTarget::new(index, count))));
+                        self.objects_was_updated = true;
+                    }
                     InitPhase::Finalize => {
                         println!("finalize");
+                        self.grid_target = None;
                         self.add_objects(Arc::new(Box::new(FullScreenImage::new(4, 5))));
                     }
                 }
@@ -86,11 +119,12 @@ impl Game for Tutorial {
         }
     }
 
-    fn on_message(&mut self, _client: u32, _message: Message, _time: u32, _sound_tx: &mut Sender<SoundType>) {
+    fn on_message(&mut self, _client: u32, _message: Message, _time: u32, _sound_tx: &mut Sender<SoundType>, _downlink_tx: &DownlinkSender) {
     }
 
     fn objects(&mut self, _time: u32) -> Vec<ObjectWrapper> {
-        let mut ret: Vec<ObjectWrapper> = self.objects.iter().map(|x| ObjectWrapper::Weak(Arc::downgrade(x))).collect();
+        let mut ret: Vec<ObjectWrapper> = self.objects.iter().chain(&self.grid_target).chain(&self.residuals).chain(&self.problems)
+            .map(|x| ObjectWrapper::Weak(Arc::downgrade(x))).collect();
         if self.was_init_state_updated() {
             let init_indicator = InitIndicator::new(self.init_state.clone());
             ret.push(ObjectWrapper::Arc(Arc::new(Box::new(init_indicator.clone()))));
@@ -6,139 +6,346 @@ use std::time::Duration;
 use tokio::net::TcpListener;
 
 use gyrogun_server::client;
-use gyrogun_server::client::init::InitPhase;
+use gyrogun_server::client::calibration::{Mode, Problem};
+use gyrogun_server::client::calibration_store::CalibrationStore;
+use gyrogun_server::client::discovery::{DISCOVERY_PORT, Discovery};
+use gyrogun_server::client::downlink::{Downlink, DownlinkRoutes, DownlinkSender, Phase};
+use gyrogun_server::client::init::{InitData, InitPhase};
+use gyrogun_server::client::latency::Latency;
+use gyrogun_server::client::liveness::Liveness;
+use gyrogun_server::client::pairing::Pairing;
 use gyrogun_server::client::position_manager::PositionManager;
-use gyrogun_server::game::{Game};
+use gyrogun_server::client::recording::Recorder;
+use gyrogun_server::client::session::{MAX_PLAYERS, ParkedSessions, Slots};
+use gyrogun_server::display::PlayerFeeds;
+use gyrogun_server::game::{Game, TICK};
 use gyrogun_server::game::balloon_game::BalloonGame;
 use gyrogun_server::game::balloon_results::BalloonResults;
+use gyrogun_server::game::lobby::Lobby;
 use gyrogun_server::game::object::ObjectWrapper;
 use gyrogun_server::game::tutorial::Tutorial;
+use gyrogun_server::player_to_color;
 use gyrogun_server::sound::SoundType;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+    let record_path = take_option(&mut args, "--record");
+    let replay_path = take_option(&mut args, "--replay");
+    let server_name = take_option(&mut args, "--name").unwrap_or(String::from("gyrogun"));
+    let pin = take_option(&mut args, "--pin");
+    let calibration = take_option(&mut args, "--calibration");
+    let calibrations_path = take_option(&mut args, "--calibrations").unwrap_or(String::from("gyrogun_calibrations.txt"));
+    /* "--fake 2" plays with that many mouse driven players instead of listening for controllers */
+    let fake = take_option(&mut args, "--fake");
+
+    /* The player count used to come first, and no window is a handful of pixels wide */
+    if args.get(1).and_then(|x| u32::from_str(x).ok()).is_some_and(|x| x <= MAX_PLAYERS) {
+        return Err(format!(
+            "The player count argument is gone, pass --fake N for mouse driven players.\nUsage: {} [width] [height] [server addr] [grace secs] [web addr]",
+            args[0]
+        ).into());
+    }
+
+    let width = args.get(1).and_then(|x| f32::from_str(x).ok()).unwrap_or(1920.0);
+    let height = args.get(2).and_then(|x| f32::from_str(x).ok()).unwrap_or(1080.0);
+    let server_addr = args.get(3).map(|x| x.as_str()).unwrap_or("0.0.0.0:11076");
+    let grace_period = args.get(4).and_then(|x| u64::from_str(x).ok()).unwrap_or(10);
+    let web_addr = args.get(5).map(|x| x.as_str()).unwrap_or("0.0.0.0:11080");
 
-    let client_count = args.get(1).and_then(|x| i32::from_str(x).ok()).unwrap_or(1);
-    let width = args.get(2).and_then(|x| f32::from_str(x).ok()).unwrap_or(1920.0);
-    let height = args.get(3).and_then(|x| f32::from_str(x).ok()).unwrap_or(1080.0);
-    let server_addr = args.get(4).and_then(|x| Some(x.as_str())).unwrap_or("0.0.0.0:11076");
+    let fake_count = match fake.as_deref() {
+        None => None,
+        Some(x) => match u32::from_str(x) {
+            Ok(count) if (1..=MAX_PLAYERS).contains(&count) => Some(count),
+            _ => return Err(format!("Fake players must be a number from 1 to {MAX_PLAYERS}, got {x}").into()),
+        },
+    };
 
     let window_size = (width, height);
 
-    let listener = TcpListener::bind(&server_addr).await?;
-    let mut pos_man = PositionManager::new();
+    /* "--pin random" picks one, otherwise the given 1 to 4 digit PIN is used */
+    let pairing = match pin.as_deref() {
+        None => Pairing::default(),
+        Some("random") => Pairing::random(),
+        Some(x) => match u32::from_str(x) {
+            Ok(pin) if (1..=9999).contains(&pin) => Pairing::new(pin),
+            _ => return Err(format!("Pairing PIN must be a number from 1 to 9999, got {x}").into()),
+        },
+    };
+    if let Some(pin) = pairing.pin() {
+        println!("Pairing required, PIN {pin:04}");
+    }
+
+    /* "--calibration 5" or "9" calibrates on a grid of that many targets, 3 is the quick left and right flow */
+    let calibration = match calibration.as_deref() {
+        None => Mode::ThreePoint,
+        Some(x) => Mode::from_str(x).map_err(|_| format!("Calibration takes 3, 5 or 9 points, got {x}"))?,
+    };
 
-    let (msg_tx, mut msg_rx) = tokio::sync::mpsc::channel(128);
+    let calibrations = CalibrationStore::open(&calibrations_path, window_size);
+    let recorder = record_path.map(|x| Recorder::create(&x)).transpose()?;
+    let mut pos_man = PositionManager::new();
+    if let Some(recorder) = &recorder {
+        pos_man.set_recorder(recorder.clone());
+    }
+    if pairing.is_required() {
+        pos_man.require_pairing();
+    }
+    let stream_stats_rx = pos_man.stats();
+    let drops_rx = pos_man.drops();
 
-    println!("Server up, waiting for {client_count} clients");
+    let (msg_tx, msg_rx) = tokio::sync::mpsc::channel(128);
 
     let (objects_tx, objects_rx) = tokio::sync::watch::channel(vec![]);
     let (time_tx, time_rx) = tokio::sync::watch::channel(0);
     let (bg_color_tx, bg_color_rx) = tokio::sync::watch::channel(macroquad::color::WHITE);
-    let (mut sounds_tx, sounds_rx) = std::sync::mpsc::channel();
-
-
+    let (sounds_tx, sounds_rx) = std::sync::mpsc::channel();
+    let (downlink_tx, downlink_rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut frame = Frame { msg_rx, sounds_tx, downlink_tx, time_tx, bg_color_tx, objects_tx };
+    let (crosshairs_tx, crosshairs_rx) = std::sync::mpsc::channel();
+    let (players_tx, mut players_rx) = tokio::sync::mpsc::unbounded_channel();
+    let (lobby_open_tx, lobby_open_rx) = tokio::sync::watch::channel(false);
+    let (match_running_tx, match_running_rx) = tokio::sync::watch::channel(false);
+
+    let mut names = HashMap::new();
     let mut next_phase_txs = HashMap::new();
     let mut done_phase_rxs = HashMap::new();
-    if client_count > 0 {
-        let mut pos_rxs: HashMap<u32, tokio::sync::watch::Receiver<(f32, f32)>> = HashMap::new();
-        let mut successes = 0;
-        while successes < client_count {
-            let (tcp_sock, addr) = listener.accept().await.unwrap();
-            let (init_data_tx, pos_rx) = pos_man.register(addr);
-
-            let (next_phase_tx, next_phase_rx) = tokio::sync::watch::channel(None);
-            let (done_phase_tx, done_phase_rx) = tokio::sync::watch::channel(None);
-            let index = client::handle(tcp_sock, addr, msg_tx.clone(), next_phase_rx, done_phase_tx, init_data_tx, window_size).await;
-
-            if let Some(index) = index {
-                pos_rxs.insert(index, pos_rx);
-                next_phase_txs.insert(index, next_phase_tx);
-                done_phase_rxs.insert(index, done_phase_rx);
-                successes += 1;
-            }
+    let mut calibration_rxs: HashMap<u32, tokio::sync::watch::Receiver<Option<InitData>>> = HashMap::new();
+    let mut problem_rxs = HashMap::new();
+    let mut sessions = HashMap::new();
+    let routes = DownlinkRoutes::default();
+    let parked = ParkedSessions::new();
+    let slots = Slots::default();
+    let liveness = Liveness::new(Duration::from_secs(grace_period));
+    let latency = Latency::new();
+    let players = PlayerFeeds {
+        crosshairs_rx,
+        stale_rx: liveness.stale(),
+        latency_rx: latency.stats(),
+    };
+    if fake_count.is_none() || replay_path.is_some() {
+        let udp_pos_man = pos_man.clone();
+        let context = client::Context {
+            window_size,
+            msg_tx: msg_tx.clone(),
+            players_tx,
+            lobby_open_rx,
+            match_running_rx,
+            parked: parked.clone(),
+            slots: slots.clone(),
+            pairing: pairing.clone(),
+            liveness: liveness.clone(),
+            latency: latency.clone(),
+            recorder,
+            pos_man: pos_man.clone(),
+            calibrations,
+        };
+
+        if let Some(replay_path) = replay_path {
+            tokio::spawn(client::recording::replay(replay_path, context));
+        } else {
+            let listener = TcpListener::bind(&server_addr).await?;
+            println!("Server up, lobby open on {server_addr}");
+
+            let server_addr = String::from(server_addr);
+            tokio::spawn(async move {
+                udp_pos_man.run(&server_addr).await;
+            });
+
+            let web_listener = TcpListener::bind(web_addr).await?;
+            let discovery = Discovery {
+                name: server_name,
+                tcp_port: listener.local_addr()?.port(),
+                web_port: web_listener.local_addr()?.port(),
+                slots: context.slots.clone(),
+                pairing: pairing.is_required(),
+                lobby_open_rx: context.lobby_open_rx.clone(),
+            };
+            tokio::spawn(async move {
+                discovery.run(&format!("0.0.0.0:{DISCOVERY_PORT}")).await;
+            });
+
+            tokio::spawn(client::listen(listener, context.clone()));
+            tokio::spawn(client::websocket::listen(web_listener, context));
         }
-
-        let server_addr = String::from(server_addr);
-        tokio::spawn(async move {
-            pos_man.run(&server_addr).await;
-        });
-        gyrogun_server::display::launch(pos_rxs, window_size, None, objects_rx, time_rx, bg_color_rx, sounds_rx);
+        gyrogun_server::display::launch(players, window_size, None, objects_rx, time_rx, bg_color_rx, sounds_rx);
     } else {
-        let fake_client_count = -client_count;
+        let fake_client_count = fake_count.unwrap_or(1) as i32;
 
         let (fake_input_tx, fake_input_rx) = std::sync::mpsc::channel();
 
         let pos_rxs = client::fake::handle(fake_input_rx, msg_tx, fake_client_count, window_size);
+        for (index, pos_rx) in pos_rxs {
+            crosshairs_tx.send((index, Some(pos_rx))).ok();
+            names.insert(index, String::new());
+        }
 
-        gyrogun_server::display::launch(pos_rxs, window_size,Some(fake_input_tx), objects_rx, time_rx, bg_color_rx, sounds_rx);
+        gyrogun_server::display::launch(players, window_size, Some(fake_input_tx), objects_rx, time_rx, bg_color_rx, sounds_rx);
     }
 
+    tokio::spawn(client::downlink::route(downlink_rx, routes.clone()));
 
     // Somehow move this to game logic using game::Message
     let mut disconnect_count = 0;
 
     loop {
+        // Lobby, players from the last match stay unless they left for good
+        let mut lobby = Lobby::new();
+        lobby.show_pin(pairing.pin());
+        for (index, name) in &names {
+            lobby.join(*index, name.clone(), !parked.contains(*index));
+            if calibration_rxs.get(index).is_some_and(|x| x.borrow().is_some()) {
+                lobby.offer_calibration(*index);
+            }
+        }
+        lobby_open_tx.send(true).ok();
+        notify_phase(&frame.downlink_tx, &names.keys().copied().collect::<Vec<_>>(), Phase::Lobby);
+
+        let mut time = 0;
+        while !lobby.should_start(time) {
+            while let Ok(player) = players_rx.try_recv() {
+                println!("Client {} joined the lobby", player.index);
+                player.downlink_tx.send(Downlink::led_color(player_to_color(player.index as usize))).ok();
+                player.downlink_tx.send(Downlink::PhaseChanged(Phase::Lobby)).ok();
+                routes.insert(player.index, player.downlink_tx);
+                crosshairs_tx.send((player.index, Some(player.pos_rx))).ok();
+                next_phase_txs.insert(player.index, player.next_phase_tx);
+                done_phase_rxs.insert(player.index, player.done_phase_rx);
+                sessions.insert(player.token, player.index);
+                lobby.join(player.index, player.name.clone(), true);
+                if player.calibration_rx.borrow().is_some() {
+                    lobby.offer_calibration(player.index);
+                }
+                calibration_rxs.insert(player.index, player.calibration_rx);
+                problem_rxs.insert(player.index, player.problem_rx);
+                names.insert(player.index, player.name);
+            }
+
+            single_frame(&mut lobby, &mut time, &mut disconnect_count, i32::MAX, &mut frame);
+            spin_sleep::sleep(TICK);
+        }
+        lobby_open_tx.send(false).ok();
+
+        let clients = lobby.players();
+        let left: Vec<u32> = names.keys().filter(|x| !clients.contains(x)).copied().collect();
+        for index in left {
+            println!("Client {index} left the lobby");
+            names.remove(&index);
+            next_phase_txs.remove(&index);
+            done_phase_rxs.remove(&index);
+            calibration_rxs.remove(&index);
+            problem_rxs.remove(&index);
+            sessions.retain(|_, x| *x != index);
+            routes.remove(index);
+            crosshairs_tx.send((index, None)).ok();
+            if let Some(token) = parked.discard(index) {
+                pos_man.remove(token);
+            }
+            slots.release(index);
+        }
+
+        let client_count = clients.len() as i32;
+        disconnect_count = 0;
+
+        /* Players who kept their calibration sit the tutorial out, and if that's everyone it's skipped */
+        let keeping = lobby.keeping_calibration();
+        let calibrating: Vec<u32> = clients.iter().filter(|x| !keeping.contains(x) && next_phase_txs.contains_key(x)).copied().collect();
+        for index in &keeping {
+            println!("Client {index} keeps its calibration");
+        }
+
         // Initialize
-        if client_count > 0 {
+        if !calibrating.is_empty() {
             println!("Starting initialize");
             let mut init_phase = Some(InitPhase::WaitMonitor);
             let mut tutorial = Tutorial::new(init_phase.unwrap());
             let mut time = 0;
+            /* Everyone calibrating, except while players whose side points were turned down redo them */
+            let mut active = calibrating.clone();
             loop {
                 for (_, recv) in &mut done_phase_rxs {
                     recv.borrow_and_update();
                 }
 
-                for (_, send) in &next_phase_txs {
-                    send.send(init_phase).unwrap();
+                /* Those waiting on a redo get no phase, so stray clicks don't count as new side points */
+                for (index, send) in next_phase_txs.iter().filter(|(x, _)| calibrating.contains(x)) {
+                    send.send(if active.contains(index) { init_phase } else { None }).unwrap();
+                }
+                if let Some(p) = init_phase {
+                    notify_phase(&frame.downlink_tx, &active, Phase::Init(p));
                 }
 
                 println!("sent next phase tx {:?}", init_phase);
 
-                let mut x: Vec<(u32, InitPhase)>;
+                /* Disconnected players, including ones dropped for going silent, don't hold the others back */
                 loop {
-                    let mut flag = false;
-                    for (idx, recv) in &done_phase_rxs {
-                        let x = recv.borrow();
-                        if x.is_none() {
-                            flag = true;
-                            tutorial.update_init_state(*idx as i32, false);
-                        }
+                    let mut waiting = false;
+                    let mut any_connected = false;
+                    for idx in keeping.iter().chain(calibrating.iter().filter(|x| !active.contains(x))) {
+                        tutorial.update_init_state(*idx as i32, true);
                     }
-
-                    if !flag {
-                        x = done_phase_rxs.iter().map(|(idx, x)| (*idx, x.borrow().unwrap())).collect();
-                        let mut flag = true;
-                        for (idx, x) in &x {
-                            if init_phase.map_or(false, |y| *x == y ) {
-                                tutorial.update_init_state(*idx as i32, true);
-                            } else {
-                                flag = false;
-                                tutorial.update_init_state(*idx as i32, false);
-                            }
-                        }
-                        if flag {
-                            break;
-                        }
+                    for (idx, recv) in done_phase_rxs.iter().filter(|(x, _)| active.contains(x)) {
+                        let connected = !parked.contains(*idx);
+                        let done = connected && *recv.borrow() == init_phase;
+                        any_connected |= connected;
+                        waiting |= connected && !done;
+                        tutorial.update_init_state(*idx as i32, done);
+                    }
+                    if any_connected && !waiting {
+                        break;
                     }
 
-                    single_frame(&mut tutorial, &mut time, &mut disconnect_count, client_count, &mut msg_rx, &mut sounds_tx, &time_tx, &bg_color_tx, &objects_tx);
-                    spin_sleep::sleep(Duration::from_millis(10));
+                    single_frame(&mut tutorial, &mut time, &mut disconnect_count, client_count, &mut frame);
+                    spin_sleep::sleep(TICK);
                 }
 
-                match x[0].1 {
+                match init_phase.unwrap() {
                     InitPhase::WaitMonitor => {
                         println!("Initphase is waitmonitor and going to exit here");
-                        init_phase = Some(InitPhase::WaitFirstPoint);
-                        tutorial.update_init_phase(InitPhase::WaitFirstPoint, time, 80);
+                        let next = match calibration {
+                            Mode::ThreePoint => InitPhase::WaitFirstPoint,
+                            Mode::Grid(count) => InitPhase::WaitGridPoint { index: 0, count },
+                        };
+                        init_phase = Some(next);
+                        tutorial.update_init_phase(next, time, 80);
                     }
                     InitPhase::WaitFirstPoint => {
                         init_phase = Some(InitPhase::WaitSecondPoint);
                         tutorial.update_init_phase(InitPhase::WaitSecondPoint, time, 80);
                     }
                     InitPhase::WaitSecondPoint => {
+                        let problems: Vec<(u32, Problem)> = active.iter()
+                            .filter(|x| !parked.contains(**x))
+                            .filter_map(|x| problem_rxs.get(x).and_then(|rx| *rx.borrow()).map(|problem| (*x, problem)))
+                            .collect();
+                        tutorial.show_problems(problems.iter().map(|(index, problem)| (*index, problem.explain())).collect());
+
+                        if problems.is_empty() {
+                            active = calibrating.clone();
+                            init_phase = Some(InitPhase::Finalize);
+                            tutorial.update_init_phase(InitPhase::Finalize, time, 80);
+                        } else {
+                            active = problems.iter().map(|(index, _)| *index).collect();
+                            for (index, problem) in &problems {
+                                println!("Client {index} redoes its side points: {problem:?}");
+                            }
+                            init_phase = Some(InitPhase::WaitFirstPoint);
+                            tutorial.update_init_phase(InitPhase::WaitFirstPoint, time, 80);
+                        }
+                    }
+                    InitPhase::WaitGridPoint { index, count } if index + 1 < count => {
+                        let next = InitPhase::WaitGridPoint { index: index + 1, count };
+                        init_phase = Some(next);
+                        tutorial.update_init_phase(next, time, 30);
+                    }
+                    InitPhase::WaitGridPoint { .. } => {
+                        let mut residuals: Vec<(u32, Option<f32>)> = calibration_rxs.iter()
+                            .filter(|(index, _)| calibrating.contains(index))
+                            .map(|(index, rx)| (*index, rx.borrow().and_then(|x| x.fit()).map(|x| x.residual)))
+                            .collect();
+                        residuals.sort_by_key(|(index, _)| *index);
+                        tutorial.show_residuals(residuals);
+
                         init_phase = Some(InitPhase::Finalize);
                         tutorial.update_init_phase(InitPhase::Finalize, time, 80);
                     }
@@ -146,8 +353,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
                         init_phase = None;
                         let time_target = time + 100;
                         while time <= time_target {
-                            single_frame(&mut tutorial, &mut time, &mut disconnect_count, client_count, &mut msg_rx, &mut sounds_tx, &time_tx, &bg_color_tx, &objects_tx);
-                            spin_sleep::sleep(Duration::from_millis(10));
+                            single_frame(&mut tutorial, &mut time, &mut disconnect_count, client_count, &mut frame);
+                            spin_sleep::sleep(TICK);
                         }
 
                         for (_, send) in &next_phase_txs {
@@ -159,8 +366,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
                 }
                 let time_target = time + 100;
                 while time <= time_target {
-                    single_frame(&mut tutorial, &mut time, &mut disconnect_count, client_count, &mut msg_rx, &mut sounds_tx, &time_tx, &bg_color_tx, &objects_tx);
-                    spin_sleep::sleep(Duration::from_millis(10));
+                    single_frame(&mut tutorial, &mut time, &mut disconnect_count, client_count, &mut frame);
+                    spin_sleep::sleep(TICK);
                 }
             }
         }
@@ -168,37 +375,59 @@ async fn main() -> Result<(), Box<dyn Error>> {
         println!("Getting into game");
 
         let game_duration = 6000;
-        let mut game = BalloonGame::new(window_size, client_count.abs() as u32, game_duration);
+        let mut game = BalloonGame::new(window_size, clients.clone(), game_duration);
         let mut time = 0;
+        notify_phase(&frame.downlink_tx, &clients, Phase::Game);
+        match_running_tx.send(true).ok();
 
         while time <= game_duration {
-            single_frame(&mut game, &mut time, &mut disconnect_count, client_count, &mut msg_rx, &mut sounds_tx, &time_tx, &bg_color_tx, &objects_tx);
-            spin_sleep::sleep(Duration::from_millis(10));
+            single_frame(&mut game, &mut time, &mut disconnect_count, client_count, &mut frame);
+            spin_sleep::sleep(TICK);
+        }
+        match_running_tx.send(false).ok();
+
+        for (session, stats) in stream_stats_rx.borrow().iter() {
+            let Some(index) = sessions.get(session) else {
+                continue;
+            };
+            println!(
+                "UDP stream of client {index}: received {}, lost {}, stale {}, unauthenticated {}",
+                stats.received, stats.lost, stats.stale, stats.unauthenticated
+            );
         }
+        let drops = *drops_rx.borrow();
+        println!(
+            "UDP datagrams dropped so far: rate limited {}, short {}, malformed {}, unknown source {}",
+            drops.rate_limited, drops.short, drops.malformed, drops.unknown_source
+        );
 
         let results_duration = 1500;
         let mut results = BalloonResults::from(window_size, &game);
         let mut time = 0;
+        notify_phase(&frame.downlink_tx, &clients, Phase::Results);
 
         while time <= results_duration {
-            single_frame(&mut results, &mut time, &mut disconnect_count, client_count, &mut msg_rx, &mut sounds_tx, &time_tx, &bg_color_tx, &objects_tx);
-            spin_sleep::sleep(Duration::from_millis(10));
+            single_frame(&mut results, &mut time, &mut disconnect_count, client_count, &mut frame);
+            spin_sleep::sleep(TICK);
         }
     }
 
 }
 
-fn single_frame<T: Game>(
-    game: &mut T,
-    time: &mut u32,
-    disconnect_count: &mut i32,
-    client_count: i32,
-    msg_rx: &mut tokio::sync::mpsc::Receiver<(u32, client::Message)>,
-    sounds_tx: &mut std::sync::mpsc::Sender<SoundType>,
-    time_tx: &tokio::sync::watch::Sender<u32>,
-    bg_color_tx: &tokio::sync::watch::Sender<macroquad::color::Color>,
-    objects_tx: &tokio::sync::watch::Sender<Vec<ObjectWrapper>>,
-) {
+/*
+    Where every frame takes the clients' messages from and hands the game's output to
+ */
+struct Frame {
+    msg_rx: tokio::sync::mpsc::Receiver<(u32, client::Message)>,
+    sounds_tx: std::sync::mpsc::Sender<SoundType>,
+    downlink_tx: DownlinkSender,
+    time_tx: tokio::sync::watch::Sender<u32>,
+    bg_color_tx: tokio::sync::watch::Sender<macroquad::color::Color>,
+    objects_tx: tokio::sync::watch::Sender<Vec<ObjectWrapper>>,
+}
+
+fn single_frame<T: Game>(game: &mut T, time: &mut u32, disconnect_count: &mut i32, client_count: i32, frame: &mut Frame) {
+    let Frame { msg_rx, sounds_tx, downlink_tx, time_tx, bg_color_tx, objects_tx } = frame;
     game.on_time(*time);
 
     loop {
@@ -213,9 +442,11 @@ fn single_frame<T: Game>(
                     println!("All clients disconnected, exiting");
                     break;
                 }
+            } else if let client::Message::Reconnect = msg {
+                *disconnect_count -= 1;
             }
 
-            game.on_message(client, msg, *time, sounds_tx);
+            game.on_message(client, msg, *time, sounds_tx, downlink_tx);
         }
     }
     time_tx.send(*time).ok();
@@ -229,3 +460,17 @@ fn single_frame<T: Game>(
     *time += 1;
 }
 
+fn notify_phase(downlink_tx: &DownlinkSender, clients: &[u32], phase: Phase) {
+    for client in clients {
+        downlink_tx.send((*client, Downlink::PhaseChanged(phase))).ok();
+    }
+}
+
+/*
+    Pulls "--name value" out of the arguments, leaving the positional ones in place
+ */
+fn take_option(args: &mut Vec<String>, name: &str) -> Option<String> {
+    let i = args.iter().position(|x| x == name)?;
+    args.remove(i);
+    (i < args.len()).then(|| args.remove(i))
+}